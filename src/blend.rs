@@ -0,0 +1,87 @@
+use palette::Blend;
+use palette::LinSrgba;
+use std::error::Error;
+use std::str::FromStr;
+
+// how an incoming color is combined with the color already in a voxel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Replace,
+    Over,
+    Overlay,
+    Add,
+    Multiply,
+    Screen,
+    Lighten,
+    Darken,
+    Subtract,
+    Xor,
+}
+
+impl BlendMode {
+    // combines $src into $dst
+    pub fn apply(self, dst: LinSrgba<f32>, src: LinSrgba<f32>) -> LinSrgba<f32> {
+        match self {
+            BlendMode::Replace => src,
+            BlendMode::Over => src.over(dst),
+            BlendMode::Overlay => dst.overlay(src),
+            BlendMode::Add => src.plus(dst),
+            BlendMode::Multiply => src.multiply(dst),
+            BlendMode::Screen => src.screen(dst),
+            BlendMode::Lighten => src.lighten(dst),
+            BlendMode::Darken => src.darken(dst),
+            // palette has no subtractive mode, so take $src (weighted by its alpha) off $dst
+            BlendMode::Subtract => LinSrgba::new(
+                (dst.red - src.red * src.alpha).max(0.0),
+                (dst.green - src.green * src.alpha).max(0.0),
+                (dst.blue - src.blue * src.alpha).max(0.0),
+                dst.alpha,
+            ),
+            BlendMode::Xor => src.xor(dst),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Replace => "replace",
+            BlendMode::Over => "over",
+            BlendMode::Overlay => "overlay",
+            BlendMode::Add => "add",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Lighten => "lighten",
+            BlendMode::Darken => "darken",
+            BlendMode::Subtract => "subtract",
+            BlendMode::Xor => "xor",
+        }
+    }
+}
+
+// overlay is what every drawing command used before blend modes were selectable
+impl Default for BlendMode {
+    fn default() -> BlendMode {
+        BlendMode::Overlay
+    }
+}
+
+impl FromStr for BlendMode {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<BlendMode, Box<Error>> {
+        Ok(match s {
+            "replace" => BlendMode::Replace,
+            "over" => BlendMode::Over,
+            "overlay" => BlendMode::Overlay,
+            "add" => BlendMode::Add,
+            "multiply" => BlendMode::Multiply,
+            "screen" => BlendMode::Screen,
+            "lighten" => BlendMode::Lighten,
+            "darken" => BlendMode::Darken,
+            "subtract" => BlendMode::Subtract,
+            "xor" => BlendMode::Xor,
+            _ => {
+                return Err(From::from(format!("no blend mode named {:?}", s)));
+            }
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use rosc::{OscMessage, OscPacket, OscType};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// seconds between the osc/ntp epoch (1900-01-01) and the unix epoch (1970-01-01)
+static NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+// messages which must be applied together, no earlier than $due. a $due of None means
+// immediately.
+#[derive(Debug)]
+pub struct Batch {
+    pub due: Option<SystemTime>,
+    pub messages: Vec<OscMessage>,
+    pub bundled: bool, // whether the messages came in a bundle, rather than on their own
+}
+
+// flattens $packet into batches. a bare message is its own immediate batch; a bundle's
+// messages form one batch, and nested bundles form batches of their own when they are due
+// later than the bundle around them.
+pub fn batches(packet: OscPacket) -> Vec<Batch> {
+    let mut out = vec![];
+    unpack(packet, None, &mut out);
+    out
+}
+
+fn unpack(packet: OscPacket, due: Option<SystemTime>, out: &mut Vec<Batch>) {
+    match packet {
+        OscPacket::Message(msg) => out.push(Batch {
+            due,
+            messages: vec![msg],
+            bundled: false,
+        }),
+        OscPacket::Bundle(bundle) => {
+            let inner_due = match (due, timetag(&bundle.timetag)) {
+                (Some(outer), Some(inner)) if inner > outer => Some(inner),
+                (Some(outer), _) => Some(outer),
+                (None, inner) => inner,
+            };
+            let mut batch = Batch {
+                due: inner_due,
+                messages: vec![],
+                bundled: true,
+            };
+            for content in bundle.content {
+                match content {
+                    OscPacket::Message(msg) => batch.messages.push(msg),
+                    bundle => {
+                        // nested bundles due at the same time are folded into this batch
+                        // where they appear, so that the whole bundle lands in one frame in
+                        // the order it was written
+                        let mut nested = vec![];
+                        unpack(bundle, inner_due, &mut nested);
+                        for b in nested {
+                            if b.due == inner_due {
+                                batch.messages.extend(b.messages);
+                            } else {
+                                out.push(b);
+                            }
+                        }
+                    }
+                }
+            }
+            out.push(batch);
+        }
+    }
+}
+
+// converts an osc timetag into wall-clock time. the special timetag 1 ("immediately") and
+// anything that isn't a time at all are None.
+pub fn timetag(tag: &OscType) -> Option<SystemTime> {
+    match *tag {
+        OscType::Time(0, 1) => None,
+        OscType::Time(secs, frac) => {
+            let secs = (secs as u64).checked_sub(NTP_UNIX_OFFSET)?;
+            let nanos = ((frac as u64 * 1_000_000_000) >> 32) as u32;
+            Some(UNIX_EPOCH + Duration::new(secs, nanos))
+        }
+        _ => None,
+    }
+}
+
+// converts wall-clock $time into an osc timetag, the inverse of timetag
+pub fn to_timetag(time: SystemTime) -> OscType {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let frac = ((since.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    OscType::Time((since.as_secs() + NTP_UNIX_OFFSET) as u32, frac as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::OscBundle;
+
+    fn msg(addr: &str) -> OscPacket {
+        OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: None,
+        })
+    }
+
+    fn bundle(timetag: OscType, content: Vec<OscPacket>) -> OscPacket {
+        OscPacket::Bundle(OscBundle { timetag, content })
+    }
+
+    fn addrs(batch: &Batch) -> Vec<&str> {
+        batch.messages.iter().map(|m| m.addr.as_ref()).collect()
+    }
+
+    #[test]
+    fn nested_bundles_due_together_keep_their_order() {
+        let now = OscType::Time(0, 1);
+        let packet = bundle(
+            now.clone(),
+            vec![
+                msg("/m1"),
+                bundle(now.clone(), vec![msg("/m2"), bundle(now, vec![msg("/m3")])]),
+                msg("/m4"),
+            ],
+        );
+        let out = batches(packet);
+        assert_eq!(out.len(), 1);
+        assert_eq!(addrs(&out[0]), vec!["/m1", "/m2", "/m3", "/m4"]);
+        assert!(out[0].bundled);
+    }
+
+    #[test]
+    fn later_nested_bundles_are_batches_of_their_own() {
+        let later = to_timetag(SystemTime::now() + Duration::from_secs(60));
+        let packet = bundle(
+            OscType::Time(0, 1),
+            vec![msg("/m1"), bundle(later, vec![msg("/m2")]), msg("/m3")],
+        );
+        let out = batches(packet);
+        assert_eq!(out.len(), 2);
+        assert_eq!(addrs(&out[0]), vec!["/m2"]);
+        assert!(out[0].due.is_some());
+        assert_eq!(addrs(&out[1]), vec!["/m1", "/m3"]);
+        assert_eq!(out[1].due, None);
+    }
+}
@@ -0,0 +1,660 @@
+use blend::BlendMode;
+use error::Error;
+use geometry;
+use geometry::Shape;
+use grid::Grid;
+use na::{Point3, Vector3};
+use palette::LinSrgba;
+use raw::RawFormat;
+use readers::Coercion;
+use registry::Registry;
+use rosc::OscMessage;
+use shaders;
+use shaders::{LinearGradient, Shader};
+use writers;
+
+// a single osc message, decoded. every address in the registry has a variant here, and
+// from_osc / to_osc convert between the two without loss.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Voxel {
+        pt: Point3<i32>,
+        clr: LinSrgba<f32>,
+    },
+    Line {
+        pt: Point3<i32>,
+        dir: Vector3<i32>,
+        clr: LinSrgba<f32>,
+    },
+    LineGrad {
+        pt: Point3<i32>,
+        dir: Vector3<i32>,
+        grad: LinearGradient,
+    },
+    Plane {
+        pt: Point3<i32>,
+        vec1: Vector3<i32>,
+        vec2: Vector3<i32>,
+        clr: LinSrgba<f32>,
+    },
+    PlaneGrad {
+        pt: Point3<i32>,
+        vec1: Vector3<i32>,
+        vec2: Vector3<i32>,
+        grad: LinearGradient,
+    },
+    Frame {
+        anchor: Point3<i32>,
+        vec1: Vector3<i32>,
+        vec2: Vector3<i32>,
+        clr: LinSrgba<f32>,
+    },
+    FrameGrad {
+        anchor: Point3<i32>,
+        vec1: Vector3<i32>,
+        vec2: Vector3<i32>,
+        grad: LinearGradient,
+    },
+    Cuboid {
+        pt: Point3<i32>,
+        vec1: Vector3<i32>,
+        vec2: Vector3<i32>,
+        vec3: Vector3<i32>,
+        clr: LinSrgba<f32>,
+    },
+    CuboidGrad {
+        pt: Point3<i32>,
+        vec1: Vector3<i32>,
+        vec2: Vector3<i32>,
+        vec3: Vector3<i32>,
+        grad: LinearGradient,
+    },
+    Sphere {
+        center: Point3<i32>,
+        p: i32,
+        clr: LinSrgba<f32>,
+    },
+    SphereGrad {
+        center: Point3<i32>,
+        p: i32,
+        grad: LinearGradient,
+    },
+    Shell {
+        center: Point3<i32>,
+        p: i32,
+        clr: LinSrgba<f32>,
+    },
+    ShellGrad {
+        center: Point3<i32>,
+        p: i32,
+        grad: LinearGradient,
+    },
+    FillSolid {
+        clr: LinSrgba<f32>,
+    },
+    FillSolidGrad {
+        grad: LinearGradient,
+    },
+    Blend {
+        mode: BlendMode,
+    },
+    GetVoxel {
+        pt: Point3<i32>,
+    },
+    GetRegion {
+        pt: Point3<i32>,
+        size: Vector3<i32>,
+    },
+    GetFrame,
+    GetInfo,
+    Schema,
+    Clear,
+    Reset,
+    Blackout,
+    FrameRaw {
+        format: RawFormat,
+        data: Vec<u8>,
+    },
+    Voxels {
+        clr: LinSrgba<f32>,
+        pts: Vec<Point3<i32>>,
+    },
+    VoxelsColored {
+        voxels: Vec<(Point3<i32>, LinSrgba<f32>)>,
+    },
+    FrameManual,
+    FrameCommit,
+    FrameAuto,
+    Undo,
+    Redo,
+    SnapshotSave {
+        name: String,
+    },
+    SnapshotLoad {
+        name: String,
+    },
+    Opacity {
+        opacity: f32,
+    },
+    Composite {
+        mode: BlendMode,
+    },
+    Visible {
+        visible: bool,
+    },
+    ZOrder {
+        z: i32,
+    },
+    // $cmd, sent to the layer called (or matching the pattern) $name
+    Layer {
+        name: String,
+        cmd: Box<Command>,
+    },
+}
+
+impl Command {
+    // parses the address and arguments of $msg, coercing arguments leniently
+    pub fn from_osc(msg: &OscMessage) -> Result<Command, Error> {
+        Command::from_osc_with(msg, Coercion::default())
+    }
+
+    // parses the address and arguments of $msg, accepting argument types per $coercion
+    pub fn from_osc_with(msg: &OscMessage, coercion: Coercion) -> Result<Command, Error> {
+        Registry::standard().decode(msg, coercion)
+    }
+
+    // the registered osc address this command is sent to. a command sent to a layer has the
+    // address it has within the layer; see osc_addr for the whole address.
+    pub fn addr(&self) -> &'static str {
+        match *self {
+            Command::Voxel { .. } => "/dsc/voxel",
+            Command::Line { .. } => "/dsc/line",
+            Command::LineGrad { .. } => "/dsc/line/grad",
+            Command::Plane { .. } => "/dsc/plane",
+            Command::PlaneGrad { .. } => "/dsc/plane/grad",
+            Command::Frame { .. } => "/dsc/frame",
+            Command::FrameGrad { .. } => "/dsc/frame/grad",
+            Command::Cuboid { .. } => "/dsc/cuboid",
+            Command::CuboidGrad { .. } => "/dsc/cuboid/grad",
+            Command::Sphere { .. } => "/dsc/sphere",
+            Command::SphereGrad { .. } => "/dsc/sphere/grad",
+            Command::Shell { .. } => "/dsc/shell",
+            Command::ShellGrad { .. } => "/dsc/shell/grad",
+            Command::FillSolid { .. } => "/fill/solid",
+            Command::FillSolidGrad { .. } => "/fill/solid/grad",
+            Command::Blend { .. } => "/blend",
+            Command::GetVoxel { .. } => "/get/voxel",
+            Command::GetRegion { .. } => "/get/region",
+            Command::GetFrame => "/get/frame",
+            Command::GetInfo => "/get/info",
+            Command::Schema => "/schema",
+            Command::Clear => "/clear",
+            Command::Reset => "/reset",
+            Command::Blackout => "/blackout",
+            Command::FrameRaw { .. } => "/frame/raw",
+            Command::Voxels { .. } => "/dsc/voxels",
+            Command::VoxelsColored { .. } => "/dsc/voxels/colored",
+            Command::FrameManual => "/frame/manual",
+            Command::FrameCommit => "/frame/commit",
+            Command::FrameAuto => "/frame/auto",
+            Command::Undo => "/undo",
+            Command::Redo => "/redo",
+            Command::SnapshotSave { .. } => "/snapshot/save",
+            Command::SnapshotLoad { .. } => "/snapshot/load",
+            Command::Opacity { .. } => "/opacity",
+            Command::Composite { .. } => "/composite",
+            Command::Visible { .. } => "/visible",
+            Command::ZOrder { .. } => "/z",
+            Command::Layer { ref cmd, .. } => cmd.addr(),
+        }
+    }
+
+    // the osc address this command is sent to, including any /layer/NAME prefix
+    pub fn osc_addr(&self) -> String {
+        match *self {
+            Command::Layer { ref name, ref cmd } => format!("/layer/{}{}", name, cmd.osc_addr()),
+            _ => self.addr().to_string(),
+        }
+    }
+
+    // encodes this command as the message from_osc would parse it from
+    pub fn to_osc(&self) -> OscMessage {
+        let mut args = vec![];
+        match *self {
+            Command::Voxel { pt, clr } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::Line { pt, dir, clr } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, dir);
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::LineGrad { pt, dir, ref grad } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, dir);
+                writers::linear_gradient(&mut args, grad);
+            }
+            Command::Plane {
+                pt,
+                vec1,
+                vec2,
+                clr,
+            } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, vec1);
+                writers::dsc_vector_3(&mut args, vec2);
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::PlaneGrad {
+                pt,
+                vec1,
+                vec2,
+                ref grad,
+            } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, vec1);
+                writers::dsc_vector_3(&mut args, vec2);
+                writers::linear_gradient(&mut args, grad);
+            }
+            Command::Frame {
+                anchor,
+                vec1,
+                vec2,
+                clr,
+            } => {
+                writers::dsc_point_3(&mut args, anchor);
+                writers::dsc_vector_3(&mut args, vec1);
+                writers::dsc_vector_3(&mut args, vec2);
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::FrameGrad {
+                anchor,
+                vec1,
+                vec2,
+                ref grad,
+            } => {
+                writers::dsc_point_3(&mut args, anchor);
+                writers::dsc_vector_3(&mut args, vec1);
+                writers::dsc_vector_3(&mut args, vec2);
+                writers::linear_gradient(&mut args, grad);
+            }
+            Command::Cuboid {
+                pt,
+                vec1,
+                vec2,
+                vec3,
+                clr,
+            } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, vec1);
+                writers::dsc_vector_3(&mut args, vec2);
+                writers::dsc_vector_3(&mut args, vec3);
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::CuboidGrad {
+                pt,
+                vec1,
+                vec2,
+                vec3,
+                ref grad,
+            } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, vec1);
+                writers::dsc_vector_3(&mut args, vec2);
+                writers::dsc_vector_3(&mut args, vec3);
+                writers::linear_gradient(&mut args, grad);
+            }
+            Command::Sphere { center, p, clr } | Command::Shell { center, p, clr } => {
+                writers::dsc_point_3(&mut args, center);
+                writers::int(&mut args, p);
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::SphereGrad {
+                center,
+                p,
+                ref grad,
+            }
+            | Command::ShellGrad {
+                center,
+                p,
+                ref grad,
+            } => {
+                writers::dsc_point_3(&mut args, center);
+                writers::int(&mut args, p);
+                writers::linear_gradient(&mut args, grad);
+            }
+            Command::FillSolid { clr } => {
+                writers::lin_srgba(&mut args, clr);
+            }
+            Command::FillSolidGrad { ref grad } => {
+                writers::linear_gradient(&mut args, grad);
+            }
+            Command::Blend { mode } => {
+                writers::string(&mut args, mode.name());
+            }
+            Command::GetVoxel { pt } => {
+                writers::dsc_point_3(&mut args, pt);
+            }
+            Command::GetRegion { pt, size } => {
+                writers::dsc_point_3(&mut args, pt);
+                writers::dsc_vector_3(&mut args, size);
+            }
+            Command::GetFrame
+            | Command::GetInfo
+            | Command::Schema
+            | Command::Clear
+            | Command::Reset
+            | Command::Blackout
+            | Command::FrameManual
+            | Command::FrameCommit
+            | Command::FrameAuto
+            | Command::Undo
+            | Command::Redo => (),
+            Command::FrameRaw {
+                ref format,
+                ref data,
+            } => {
+                writers::string(&mut args, &format.name());
+                writers::blob(&mut args, data);
+            }
+            Command::Voxels { clr, ref pts } => {
+                writers::lin_srgba(&mut args, clr);
+                for pt in pts {
+                    writers::dsc_point_3(&mut args, *pt);
+                }
+            }
+            Command::VoxelsColored { ref voxels } => {
+                for &(pt, clr) in voxels {
+                    writers::dsc_point_3(&mut args, pt);
+                    writers::lin_srgba(&mut args, clr);
+                }
+            }
+            Command::SnapshotSave { ref name } | Command::SnapshotLoad { ref name } => {
+                writers::string(&mut args, name);
+            }
+            Command::Opacity { opacity } => {
+                writers::float(&mut args, opacity);
+            }
+            Command::Composite { mode } => {
+                writers::string(&mut args, mode.name());
+            }
+            Command::Visible { visible } => {
+                writers::int(&mut args, visible as i32);
+            }
+            Command::ZOrder { z } => {
+                writers::int(&mut args, z);
+            }
+            Command::Layer { ref cmd, .. } => {
+                args = cmd.to_osc().args.unwrap_or_default();
+            }
+        }
+        OscMessage {
+            addr: self.osc_addr(),
+            args: Some(args),
+        }
+    }
+
+    // the cells this command paints within $grid and the colors to paint them, or None if
+    // it doesn't draw anything
+    pub fn shape_and_shader(&self, grid: &Grid) -> Option<(Shape, Box<Shader>)> {
+        Some(match *self {
+            Command::Voxel { pt, clr } => (vec![pt], shaders::solid(clr)),
+            Command::Line { pt, dir, clr } => {
+                (geometry::discrete_line(pt, dir), shaders::solid(clr))
+            }
+            Command::LineGrad { pt, dir, ref grad } => {
+                (geometry::discrete_line(pt, dir), grad.shader())
+            }
+            Command::Plane {
+                pt,
+                vec1,
+                vec2,
+                clr,
+            } => (
+                geometry::discrete_plane(pt, vec1, vec2),
+                shaders::solid(clr),
+            ),
+            Command::PlaneGrad {
+                pt,
+                vec1,
+                vec2,
+                ref grad,
+            } => (geometry::discrete_plane(pt, vec1, vec2), grad.shader()),
+            Command::Frame {
+                anchor,
+                vec1,
+                vec2,
+                clr,
+            } => (
+                geometry::discrete_frame(anchor, vec1, vec2),
+                shaders::solid(clr),
+            ),
+            Command::FrameGrad {
+                anchor,
+                vec1,
+                vec2,
+                ref grad,
+            } => (geometry::discrete_frame(anchor, vec1, vec2), grad.shader()),
+            Command::Cuboid {
+                pt,
+                vec1,
+                vec2,
+                vec3,
+                clr,
+            } => (
+                geometry::discrete_cuboid(pt, vec1, vec2, vec3),
+                shaders::solid(clr),
+            ),
+            Command::CuboidGrad {
+                pt,
+                vec1,
+                vec2,
+                vec3,
+                ref grad,
+            } => (
+                geometry::discrete_cuboid(pt, vec1, vec2, vec3),
+                grad.shader(),
+            ),
+            Command::Sphere { center, p, clr } => {
+                (geometry::discrete_sphere(center, p), shaders::solid(clr))
+            }
+            Command::SphereGrad {
+                center,
+                p,
+                ref grad,
+            } => (geometry::discrete_sphere(center, p), grad.shader()),
+            Command::Shell { center, p, clr } => {
+                (geometry::discrete_shell(center, p), shaders::solid(clr))
+            }
+            Command::ShellGrad {
+                center,
+                p,
+                ref grad,
+            } => (geometry::discrete_shell(center, p), grad.shader()),
+            Command::FillSolid { clr } => (geometry::all_cells(grid), shaders::solid(clr)),
+            Command::FillSolidGrad { ref grad } => (geometry::all_cells(grid), grad.shader()),
+            Command::Voxels { clr, ref pts } => (pts.clone(), shaders::solid(clr)),
+            Command::Blend { .. }
+            | Command::GetVoxel { .. }
+            | Command::GetRegion { .. }
+            | Command::GetFrame
+            | Command::GetInfo
+            | Command::Schema
+            | Command::Clear
+            | Command::Reset
+            | Command::Blackout
+            | Command::FrameRaw { .. }
+            | Command::VoxelsColored { .. }
+            | Command::FrameManual
+            | Command::FrameCommit
+            | Command::FrameAuto
+            | Command::Undo
+            | Command::Redo
+            | Command::SnapshotSave { .. }
+            | Command::SnapshotLoad { .. }
+            | Command::Opacity { .. }
+            | Command::Composite { .. }
+            | Command::Visible { .. }
+            | Command::ZOrder { .. }
+            | Command::Layer { .. } => {
+                return None;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use registry::Entry;
+    use rosc::OscType;
+
+    static BLEND_MODES: &'static [&'static str] = &[
+        "replace", "over", "overlay", "add", "multiply", "screen", "lighten", "darken", "subtract",
+        "xor",
+    ];
+    static RAW_ORDERS: &'static [&'static str] = &["rgb", "bgr", "rgba", "argb", "bgra"];
+    static RAW_SAMPLES: &'static [&'static str] = &["u8", "u16", "f32"];
+
+    // xorshift, so that every run checks the same arbitrary values
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn int(&mut self) -> i32 {
+            self.next() as i32
+        }
+
+        // any finite float. NaN would never compare equal to itself.
+        fn float(&mut self) -> f32 {
+            loop {
+                let f = f32::from_bits(self.next() as u32);
+                if f.is_finite() {
+                    return f;
+                }
+            }
+        }
+
+        fn unit(&mut self) -> f32 {
+            self.below(1001) as f32 / 1000.0
+        }
+
+        fn pick(&mut self, from: &[&'static str]) -> &'static str {
+            from[self.below(from.len())]
+        }
+    }
+
+    // a string argument $addr accepts
+    fn arbitrary_string(rng: &mut Rng, addr: &str) -> String {
+        match addr {
+            "/blend" | "/composite" => rng.pick(BLEND_MODES).to_string(),
+            "/frame/raw" => format!("{}_{}", rng.pick(RAW_ORDERS), rng.pick(RAW_SAMPLES)),
+            "/snapshot/save" | "/snapshot/load" => format!("snapshot{}", rng.below(1000)),
+            _ => panic!("no arbitrary string for {}", addr),
+        }
+    }
+
+    fn push_args(rng: &mut Rng, addr: &str, typetag: &str, args: &mut Vec<OscType>) {
+        for tag in typetag.chars() {
+            args.push(match tag {
+                'i' => OscType::Int(rng.int()),
+                'f' if addr == "/opacity" => OscType::Float(rng.unit()),
+                'f' => OscType::Float(rng.float()),
+                's' => OscType::String(arbitrary_string(rng, addr)),
+                'b' => OscType::Blob((0..rng.below(64)).map(|_| rng.next() as u8).collect()),
+                _ => panic!("no arbitrary {:?} argument for {}", tag, addr),
+            });
+        }
+    }
+
+    // a message $entry decodes, with arbitrary arguments. a repeated group like (iii)* at
+    // the end of the typetag appears any number of times, none included.
+    fn arbitrary_msg(rng: &mut Rng, entry: &Entry) -> OscMessage {
+        let mut args = vec![];
+        match entry.typetag.find('(') {
+            Some(open) => {
+                push_args(rng, entry.addr, &entry.typetag[..open], &mut args);
+                let group = &entry.typetag[open + 1..entry.typetag.len() - 2];
+                for _ in 0..rng.below(5) {
+                    push_args(rng, entry.addr, group, &mut args);
+                }
+            }
+            None => push_args(rng, entry.addr, entry.typetag, &mut args),
+        }
+        OscMessage {
+            addr: entry.addr.to_string(),
+            args: Some(args),
+        }
+    }
+
+    fn arbitrary_command(rng: &mut Rng, entry: &Entry) -> Command {
+        let msg = arbitrary_msg(rng, entry);
+        match Command::from_osc(&msg) {
+            Ok(cmd) => cmd,
+            Err(e) => panic!("{:?} didn't decode: {}", msg, e),
+        }
+    }
+
+    fn assert_round_trips(cmd: Command) {
+        assert_eq!(Command::from_osc(&cmd.to_osc()), Ok(cmd));
+    }
+
+    #[test]
+    fn every_address_round_trips() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for entry in Registry::standard().entries() {
+            for _ in 0..100 {
+                let cmd = arbitrary_command(&mut rng, entry);
+                assert_eq!(cmd.addr(), entry.addr);
+                assert_round_trips(cmd);
+            }
+        }
+    }
+
+    #[test]
+    fn every_address_round_trips_on_a_layer() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for entry in Registry::standard().entries() {
+            for &name in &["fx", "layer", "*"] {
+                let cmd = Command::Layer {
+                    name: name.to_string(),
+                    cmd: Box::new(arbitrary_command(&mut rng, entry)),
+                };
+                assert_eq!(cmd.to_osc().addr, format!("/layer/{}{}", name, entry.addr));
+                assert_round_trips(cmd);
+            }
+        }
+    }
+
+    #[test]
+    fn variable_length_commands_round_trip() {
+        let clr = LinSrgba::new(0.25, 0.5, 0.75, 1.0);
+        for n in &[0, 1, 2, 512] {
+            let pts: Vec<Point3<i32>> = (0..*n).map(|i| Point3::new(i, -i, i * 2)).collect();
+            assert_round_trips(Command::Voxels {
+                clr,
+                pts: pts.clone(),
+            });
+            assert_round_trips(Command::VoxelsColored {
+                voxels: pts.into_iter().map(|pt| (pt, clr)).collect(),
+            });
+        }
+        for &len in &[0, 1, 1536, 8192] {
+            assert_round_trips(Command::FrameRaw {
+                format: "bgra_u16".parse().unwrap(),
+                data: (0..len).map(|i| i as u8).collect(),
+            });
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::error::Error;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use voxel_preview::framing::Framing;
+use voxel_preview::grid::Grid;
+use voxel_preview::readers::Coercion;
+
+// somewhere to receive osc datagrams from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    // an ipv4 or ipv6 address, e.g. 127.0.0.1:1234 or [::1]:1234
+    Udp(SocketAddr),
+    // a multicast group to join, e.g. 239.255.0.1:1234 or [ff02::1234]:1234
+    Multicast(SocketAddr),
+    // a unix datagram socket. written as a path containing a '/', e.g. ./cube.sock, so that
+    // a mistyped address isn't taken for a file name.
+    Unix(PathBuf),
+}
+
+impl FromStr for Endpoint {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Endpoint, Box<Error>> {
+        if let Ok(addr) = SocketAddr::from_str(s) {
+            if addr.ip().is_multicast() {
+                Ok(Endpoint::Multicast(addr))
+            } else {
+                Ok(Endpoint::Udp(addr))
+            }
+        } else if s.contains('/') {
+            Ok(Endpoint::Unix(PathBuf::from(s)))
+        } else {
+            Err(From::from(format!(
+                "{:?} is neither an IP:PORT nor a socket path",
+                s
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Endpoint::Udp(addr) => write!(f, "{}", addr),
+            Endpoint::Multicast(addr) => write!(f, "{} (multicast)", addr),
+            Endpoint::Unix(ref path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+// the network interface multicast groups are joined on. ipv4 names it by one of its
+// addresses and ipv6 by its index; left unspecified, the os picks one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interface {
+    pub v4: Ipv4Addr,
+    pub v6: u32,
+}
+
+impl Default for Interface {
+    fn default() -> Interface {
+        Interface {
+            v4: Ipv4Addr::new(0, 0, 0, 0),
+            v6: 0,
+        }
+    }
+}
+
+// startup options, parsed from the command line
+#[derive(Debug)]
+pub struct Config {
+    pub listen: Vec<Endpoint>, // where to receive osc datagrams; never empty
+    pub grid: Grid,
+    pub error_replies: bool, // whether senders are told about bad messages via /error
+    pub coercion: Coercion,  // which argument types are accepted
+    pub tcp: Vec<SocketAddr>, // where to also accept osc over tcp
+    pub framing: Framing,    // how packets are delimited over tcp
+    pub interface: Interface, // where multicast groups are joined
+    // a snapshot file to start from, rather than the rainbow
+    pub load: Option<PathBuf>,
+    // where /snapshot/save and /snapshot/load keep their files
+    pub snapshot_dir: PathBuf,
+    // where to keep the session journal, if anywhere
+    pub journal: Option<PathBuf>,
+    // the least time between checkpoints of the journal
+    pub journal_interval: Duration,
+    // whether received commands are logged to the journal too
+    pub journal_commands: bool,
+    // whether to start from the journal's latest checkpoint, if it has one
+    pub restore: bool,
+}
+
+impl Config {
+    pub fn usage(program: &str) -> String {
+        format!(
+            "Usage: {} [IP:PORT] [--listen IP:PORT|PATH]... [--size WIDTHxHEIGHTxDEPTH] [--error-replies] [--strict] [--tcp IP:PORT]... [--framing slip|length] [--interface IP|INDEX] [--load FILE] [--snapshots DIR] [--journal DIR] [--journal-interval SECS] [--journal-commands] [--restore]",
+            program
+        )
+    }
+
+    // parses $args, which excludes the program name
+    pub fn from_args(args: &[String]) -> Result<Config, Box<Error>> {
+        let mut listen = vec![];
+        let mut grid = Grid::default();
+        let mut error_replies = false;
+        let mut coercion = Coercion::default();
+        let mut tcp = vec![];
+        let mut framing = Framing::default();
+        let mut interface = Interface::default();
+        let mut load = None;
+        let mut snapshot_dir = PathBuf::from("snapshots");
+        let mut journal = None;
+        let mut journal_interval = Duration::from_secs(5);
+        let mut journal_commands = false;
+        let mut restore = false;
+
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            match arg.as_ref() {
+                "--size" => {
+                    grid = match it.next() {
+                        Some(size) => size.parse()?,
+                        None => {
+                            return Err(From::from("--size needs a value".to_string()));
+                        }
+                    };
+                }
+                "--error-replies" => {
+                    error_replies = true;
+                }
+                "--strict" => {
+                    coercion = Coercion::Strict;
+                }
+                "--listen" => match it.next() {
+                    Some(endpoint) => listen.push(endpoint.parse()?),
+                    None => {
+                        return Err(From::from("--listen needs a value".to_string()));
+                    }
+                },
+                "--tcp" => match it.next() {
+                    Some(addr) => tcp.push(SocketAddr::from_str(addr)?),
+                    None => {
+                        return Err(From::from("--tcp needs a value".to_string()));
+                    }
+                },
+                "--framing" => {
+                    framing = match it.next() {
+                        Some(framing) => framing.parse()?,
+                        None => {
+                            return Err(From::from("--framing needs a value".to_string()));
+                        }
+                    };
+                }
+                "--load" => match it.next() {
+                    Some(path) => load = Some(PathBuf::from(path)),
+                    None => {
+                        return Err(From::from("--load needs a value".to_string()));
+                    }
+                },
+                "--snapshots" => match it.next() {
+                    Some(dir) => snapshot_dir = PathBuf::from(dir),
+                    None => {
+                        return Err(From::from("--snapshots needs a value".to_string()));
+                    }
+                },
+                "--journal" => match it.next() {
+                    Some(dir) => journal = Some(PathBuf::from(dir)),
+                    None => {
+                        return Err(From::from("--journal needs a value".to_string()));
+                    }
+                },
+                "--journal-interval" => match it.next() {
+                    Some(secs) => journal_interval = Duration::from_secs(secs.parse()?),
+                    None => {
+                        return Err(From::from("--journal-interval needs a value".to_string()));
+                    }
+                },
+                "--journal-commands" => {
+                    journal_commands = true;
+                }
+                "--restore" => {
+                    restore = true;
+                }
+                // an ipv4 address sets the interface for ipv4 groups, and a number the one
+                // for ipv6 groups
+                "--interface" => match it.next() {
+                    Some(iface) => {
+                        if let Ok(v4) = Ipv4Addr::from_str(iface) {
+                            interface.v4 = v4;
+                        } else {
+                            interface.v6 = iface.parse()?;
+                        }
+                    }
+                    None => {
+                        return Err(From::from("--interface needs a value".to_string()));
+                    }
+                },
+                // a bare endpoint is shorthand for --listen
+                _ if !arg.starts_with("--") => {
+                    listen.push(arg.parse()?);
+                }
+                _ => {
+                    return Err(From::from(format!("unexpected argument {:?}", arg)));
+                }
+            }
+        }
+
+        if listen.is_empty() {
+            return Err(From::from("missing an address to listen on".to_string()));
+        }
+        if journal.is_none() && (journal_commands || restore) {
+            return Err(From::from(
+                "--journal-commands and --restore need --journal".to_string(),
+            ));
+        }
+        Ok(Config {
+            listen,
+            grid,
+            error_replies,
+            coercion,
+            tcp,
+            framing,
+            interface,
+            load,
+            snapshot_dir,
+            journal,
+            journal_interval,
+            journal_commands,
+            restore,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    fn endpoint(s: &str) -> Option<Endpoint> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn parses_endpoints() {
+        for &(s, ref expected) in &[
+            (
+                "127.0.0.1:1234",
+                Endpoint::Udp(([127, 0, 0, 1], 1234).into()),
+            ),
+            ("0.0.0.0:0", Endpoint::Udp(([0, 0, 0, 0], 0).into())),
+            (
+                "[::1]:1234",
+                Endpoint::Udp((Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1), 1234).into()),
+            ),
+            ("./cube.sock", Endpoint::Unix(PathBuf::from("./cube.sock"))),
+            (
+                "/tmp/cube.sock",
+                Endpoint::Unix(PathBuf::from("/tmp/cube.sock")),
+            ),
+        ] {
+            assert_eq!(endpoint(s).as_ref(), Some(expected), "parsing {:?}", s);
+        }
+    }
+
+    #[test]
+    fn multicast_groups_are_joined_rather_than_bound() {
+        for &(s, ref expected) in &[
+            (
+                "239.255.0.1:1234",
+                Endpoint::Multicast(([239, 255, 0, 1], 1234).into()),
+            ),
+            (
+                "[ff02::1234]:1234",
+                Endpoint::Multicast((Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x1234), 1234).into()),
+            ),
+        ] {
+            assert_eq!(endpoint(s).as_ref(), Some(expected), "parsing {:?}", s);
+        }
+    }
+
+    // the interface --interface $value sets, or None if it's rejected
+    fn interface(value: &str) -> Option<Interface> {
+        let args: Vec<String> = vec![
+            "239.255.0.1:1234".into(),
+            "--interface".into(),
+            value.into(),
+        ];
+        Config::from_args(&args).ok().map(|config| config.interface)
+    }
+
+    #[test]
+    fn parses_interfaces() {
+        let v4 = Ipv4Addr::new(192, 168, 1, 2);
+        for &(value, expected) in &[
+            ("192.168.1.2", Interface { v4, v6: 0 }),
+            (
+                "3",
+                Interface {
+                    v6: 3,
+                    ..Interface::default()
+                },
+            ),
+            ("0", Interface::default()),
+        ] {
+            assert_eq!(interface(value), Some(expected), "parsing {:?}", value);
+        }
+        for value in &["", "eth0", "-1", "::1", "192.168.1"] {
+            assert_eq!(interface(value), None, "parsing {:?}", value);
+        }
+
+        // an index and an address can both be given, each setting the interface for its own
+        // kind of group
+        let args: Vec<String> = ["[ff02::1]:1", "--interface", "4", "--interface", "10.0.0.1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            Config::from_args(&args).ok().map(|config| config.interface),
+            Some(Interface {
+                v4: Ipv4Addr::new(10, 0, 0, 1),
+                v6: 4,
+            })
+        );
+        assert!(Config::from_args(&args[..2]).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_endpoints() {
+        for s in &[
+            "",
+            "cube.sock",
+            "localhost:1234",
+            "127.0.0.1",
+            "127.0.0.1:99999",
+        ] {
+            assert_eq!(endpoint(s), None, "parsing {:?}", s);
+        }
+    }
+}
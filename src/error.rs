@@ -0,0 +1,120 @@
+use grid::Grid;
+use na::Point3;
+use rosc::{OscMessage, OscType};
+use std::error;
+use std::fmt;
+
+// everything that can go wrong decoding or applying a command. argument indices count
+// from 0 and refer to the osc arguments of the offending message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    UnknownAddress(String),
+    MissingArg {
+        index: usize,
+        name: &'static str,
+    },
+    WrongType {
+        index: usize,
+        name: &'static str,
+        expected: &'static str,
+        got: &'static str,
+    },
+    OutOfBounds {
+        pt: Point3<i32>,
+        grid: Grid,
+    },
+    InvalidValue {
+        index: usize,
+        name: &'static str,
+        value: String,
+    },
+    // a query or /snapshot/save was sent to a layer which doesn't exist
+    UnknownLayer(String),
+    // a file couldn't be read or written, or didn't hold what it should
+    File {
+        path: String,
+        reason: String,
+    },
+}
+
+impl Error {
+    // the index of the argument at fault, if there is one
+    pub fn arg_index(&self) -> Option<usize> {
+        match *self {
+            Error::MissingArg { index, .. }
+            | Error::WrongType { index, .. }
+            | Error::InvalidValue { index, .. } => Some(index),
+            Error::UnknownAddress(_)
+            | Error::UnknownLayer(_)
+            | Error::OutOfBounds { .. }
+            | Error::File { .. } => None,
+        }
+    }
+
+    // /error addr index message, reporting this error to whoever sent $addr. index is -1
+    // when no single argument is at fault.
+    pub fn to_osc(&self, addr: &str) -> OscMessage {
+        OscMessage {
+            addr: "/error".to_string(),
+            args: Some(vec![
+                OscType::String(addr.to_string()),
+                OscType::Int(self.arg_index().map_or(-1, |i| i as i32)),
+                OscType::String(self.to_string()),
+            ]),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownAddress(ref addr) => write!(f, "no match for addr {:?}", addr),
+            Error::UnknownLayer(ref name) => write!(f, "no layer called {:?}", name),
+            Error::MissingArg { index, name } => {
+                write!(f, "missing argument <{}> at index {}", name, index)
+            }
+            Error::WrongType {
+                index,
+                name,
+                expected,
+                got,
+            } => write!(
+                f,
+                "argument <{}> at index {} should be {}, not {}",
+                name, index, expected, got
+            ),
+            Error::OutOfBounds { pt, grid } => write!(
+                f,
+                "({}, {}, {}) not in range (0, 0, 0)..({}, {}, {})",
+                pt.x, pt.y, pt.z, grid.width, grid.height, grid.depth
+            ),
+            Error::InvalidValue {
+                index,
+                name,
+                ref value,
+            } => write!(
+                f,
+                "argument <{}> at index {} has invalid value {}",
+                name, index, value
+            ),
+            Error::File {
+                ref path,
+                ref reason,
+            } => write!(f, "couldn't use {}: {}", path, reason),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::UnknownAddress(_) => "unknown address",
+            Error::UnknownLayer(_) => "unknown layer",
+            Error::MissingArg { .. } => "missing argument",
+            Error::WrongType { .. } => "argument of the wrong type",
+            Error::OutOfBounds { .. } => "point out of bounds",
+            Error::InvalidValue { .. } => "invalid argument value",
+            Error::File { .. } => "file error",
+        }
+    }
+}
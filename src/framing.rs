@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::str::FromStr;
+
+// SLIP special bytes, per RFC 1055
+static END: u8 = 0xC0;
+static ESC: u8 = 0xDB;
+static ESC_END: u8 = 0xDC;
+static ESC_ESC: u8 = 0xDD;
+
+// packets larger than this are dropped rather than buffered, so that a stream which never
+// ends a frame can't grow without bound
+pub static MAX_PACKET: usize = 16 * 1024 * 1024;
+
+// how osc packets are delimited on a stream transport like tcp
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+    // osc 1.1: each packet is SLIP encoded, with an END byte on both sides
+    Slip,
+    // osc 1.0: each packet is preceded by its length, as a big-endian int32
+    LengthPrefixed,
+}
+
+impl Framing {
+    // wraps $packet for sending
+    pub fn encode(self, packet: &[u8]) -> Vec<u8> {
+        match self {
+            Framing::Slip => {
+                let mut out = Vec::with_capacity(packet.len() + 2);
+                out.push(END);
+                for &b in packet {
+                    if b == END {
+                        out.push(ESC);
+                        out.push(ESC_END);
+                    } else if b == ESC {
+                        out.push(ESC);
+                        out.push(ESC_ESC);
+                    } else {
+                        out.push(b);
+                    }
+                }
+                out.push(END);
+                out
+            }
+            Framing::LengthPrefixed => {
+                let len = packet.len() as u32;
+                let mut out = Vec::with_capacity(packet.len() + 4);
+                out.push((len >> 24) as u8);
+                out.push((len >> 16) as u8);
+                out.push((len >> 8) as u8);
+                out.push(len as u8);
+                out.extend_from_slice(packet);
+                out
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::Slip => "slip",
+            Framing::LengthPrefixed => "length",
+        }
+    }
+}
+
+impl Default for Framing {
+    fn default() -> Framing {
+        Framing::Slip
+    }
+}
+
+impl FromStr for Framing {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Framing, Box<Error>> {
+        match s {
+            "slip" => Ok(Framing::Slip),
+            "length" => Ok(Framing::LengthPrefixed),
+            _ => Err(From::from(format!("no framing named {:?}", s))),
+        }
+    }
+}
+
+// splits a byte stream back into packets. bytes can be pushed in chunks of any size, and
+// packets come out as soon as they are complete.
+pub struct Deframer {
+    framing: Framing,
+    buf: Vec<u8>,
+    escaped: bool,  // slip: the last byte was ESC
+    oversize: bool, // the packet being read is over MAX_PACKET and will be dropped
+}
+
+impl Deframer {
+    pub fn new(framing: Framing) -> Deframer {
+        Deframer {
+            framing,
+            buf: vec![],
+            escaped: false,
+            oversize: false,
+        }
+    }
+
+    // consumes $bytes, returning every packet they complete
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        match self.framing {
+            Framing::Slip => self.push_slip(bytes),
+            Framing::LengthPrefixed => self.push_length_prefixed(bytes),
+        }
+    }
+
+    fn push_slip(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut packets = vec![];
+        for &b in bytes {
+            if b == END {
+                // empty frames come from the END which starts each packet, and are skipped
+                if !self.buf.is_empty() && !self.oversize {
+                    packets.push(self.buf.clone());
+                }
+                self.buf.clear();
+                self.escaped = false;
+                self.oversize = false;
+                continue;
+            }
+            let b = if self.escaped {
+                self.escaped = false;
+                if b == ESC_END {
+                    END
+                } else if b == ESC_ESC {
+                    ESC
+                } else {
+                    b
+                }
+            } else if b == ESC {
+                self.escaped = true;
+                continue;
+            } else {
+                b
+            };
+            if self.buf.len() < MAX_PACKET {
+                self.buf.push(b);
+            } else {
+                self.oversize = true;
+            }
+        }
+        packets
+    }
+
+    fn push_length_prefixed(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut packets = vec![];
+        if self.oversize {
+            return packets;
+        }
+        self.buf.extend_from_slice(bytes);
+        loop {
+            if self.buf.len() < 4 {
+                break;
+            }
+            let len = (self.buf[0] as usize) << 24
+                | (self.buf[1] as usize) << 16
+                | (self.buf[2] as usize) << 8
+                | self.buf[3] as usize;
+            if len > MAX_PACKET {
+                // there is no way to find the next packet after a bad length
+                self.buf.clear();
+                self.oversize = true;
+                break;
+            }
+            if self.buf.len() < 4 + len {
+                break;
+            }
+            packets.push(self.buf[4..4 + len].to_vec());
+            self.buf.drain(..4 + len);
+        }
+        packets
+    }
+
+    // whether the stream has been abandoned because of a packet it couldn't frame
+    pub fn is_broken(&self) -> bool {
+        self.framing == Framing::LengthPrefixed && self.oversize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a packet holding every byte slip has to escape, between ordinary ones
+    fn awkward_packet() -> Vec<u8> {
+        vec![1, END, 2, ESC, 3, ESC, ESC_END, END, END, 4]
+    }
+
+    // pushes $bytes one at a time, collecting every packet they complete
+    fn push_bytewise(deframer: &mut Deframer, bytes: &[u8]) -> Vec<Vec<u8>> {
+        bytes.iter().flat_map(|&b| deframer.push(&[b])).collect()
+    }
+
+    #[test]
+    fn slip_round_trips() {
+        let packet = awkward_packet();
+        let mut deframer = Deframer::new(Framing::Slip);
+        assert_eq!(deframer.push(&Framing::Slip.encode(&packet)), vec![packet]);
+    }
+
+    #[test]
+    fn slip_escapes_split_across_pushes() {
+        let packet = awkward_packet();
+        let encoded = Framing::Slip.encode(&packet);
+        for split in 0..encoded.len() {
+            let mut deframer = Deframer::new(Framing::Slip);
+            let mut packets = deframer.push(&encoded[..split]);
+            packets.extend(deframer.push(&encoded[split..]));
+            assert_eq!(packets, vec![packet.clone()], "split at {}", split);
+        }
+        let mut deframer = Deframer::new(Framing::Slip);
+        assert_eq!(push_bytewise(&mut deframer, &encoded), vec![packet]);
+    }
+
+    #[test]
+    fn slip_skips_empty_frames() {
+        let mut stream = vec![END, END];
+        stream.extend(Framing::Slip.encode(&[1, 2]));
+        stream.extend(Framing::Slip.encode(&[3]));
+        let mut deframer = Deframer::new(Framing::Slip);
+        assert_eq!(deframer.push(&stream), vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn slip_drops_oversize_packets_and_carries_on() {
+        let mut stream = Framing::Slip.encode(&vec![7; MAX_PACKET + 1]);
+        stream.extend(Framing::Slip.encode(&[1]));
+        let mut deframer = Deframer::new(Framing::Slip);
+        assert_eq!(deframer.push(&stream), vec![vec![1]]);
+        assert!(!deframer.is_broken());
+    }
+
+    #[test]
+    fn length_prefixed_round_trips_in_any_chunks() {
+        let packets = vec![awkward_packet(), vec![], vec![9; 300]];
+        let stream: Vec<u8> = packets
+            .iter()
+            .flat_map(|p| Framing::LengthPrefixed.encode(p))
+            .collect();
+        let mut deframer = Deframer::new(Framing::LengthPrefixed);
+        assert_eq!(deframer.push(&stream), packets);
+        let mut deframer = Deframer::new(Framing::LengthPrefixed);
+        assert_eq!(push_bytewise(&mut deframer, &stream), packets);
+        assert!(!deframer.is_broken());
+    }
+
+    #[test]
+    fn length_prefixed_breaks_on_an_oversize_length() {
+        let mut stream = Framing::LengthPrefixed.encode(&[1]);
+        let len = MAX_PACKET as u32 + 1;
+        stream.extend(&[
+            (len >> 24) as u8,
+            (len >> 16) as u8,
+            (len >> 8) as u8,
+            len as u8,
+        ]);
+        stream.extend(Framing::LengthPrefixed.encode(&[2]));
+        let mut deframer = Deframer::new(Framing::LengthPrefixed);
+        assert_eq!(deframer.push(&stream), vec![vec![1]]);
+        assert!(deframer.is_broken());
+        assert!(deframer
+            .push(&Framing::LengthPrefixed.encode(&[3]))
+            .is_empty());
+    }
+}
@@ -1,7 +1,10 @@
-use CUBE_WIDTH;
 use bresenham3d;
+use grid::Grid;
 use na::{distance, Point3, Vector3};
 
+// the cells covered by a drawing command
+pub type Shape = Vec<Point3<i32>>;
+
 pub fn discrete_line(pt: Point3<i32>, dir: Vector3<i32>) -> Vec<Point3<i32>> {
     bresenham3d::line(pt, pt + dir)
 }
@@ -86,14 +89,6 @@ pub fn discrete_shell(center: Point3<i32>, p: i32) -> Vec<Point3<i32>> {
     cells
 }
 
-pub fn all_cells() -> Vec<Point3<i32>> {
-    let mut cells = vec![];
-    for i in 0..CUBE_WIDTH {
-        for j in 0..CUBE_WIDTH {
-            for k in 0..CUBE_WIDTH {
-                cells.push(Point3::new(i, j, k));
-            }
-        }
-    }
-    cells
+pub fn all_cells(grid: &Grid) -> Vec<Point3<i32>> {
+    grid.cells()
 }
@@ -0,0 +1,138 @@
+use error::Error;
+use na::Point3;
+use std::error;
+use std::str::FromStr;
+
+// the dimensions of the cube, chosen at startup. $width, $height and $depth are the
+// extents along x, y and z respectively; z is drawn as the vertical axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+    pub width: i32,
+    pub height: i32,
+    pub depth: i32,
+}
+
+impl Grid {
+    pub fn new(width: i32, height: i32, depth: i32) -> Grid {
+        Grid {
+            width,
+            height,
+            depth,
+        }
+    }
+
+    // the number of cells in the grid
+    pub fn len(&self) -> usize {
+        (self.width * self.height * self.depth) as usize
+    }
+
+    // the longest of the three extents
+    pub fn max_extent(&self) -> i32 {
+        self.width.max(self.height).max(self.depth)
+    }
+
+    pub fn contains(&self, pt: Point3<i32>) -> bool {
+        (0..self.width).contains(pt.x)
+            && (0..self.height).contains(pt.y)
+            && (0..self.depth).contains(pt.z)
+    }
+
+    // tests xyz range for $pt and maps it to an offset into a flat x-major array
+    pub fn index(&self, pt: Point3<i32>) -> Result<usize, Error> {
+        if self.contains(pt) {
+            Ok(((pt.x * self.height + pt.y) * self.depth + pt.z) as usize)
+        } else {
+            Err(Error::OutOfBounds { pt, grid: *self })
+        }
+    }
+
+    // every cell in the grid, in the same x-major order as $index
+    pub fn cells(&self) -> Vec<Point3<i32>> {
+        let mut cells = vec![];
+        for i in 0..self.width {
+            for j in 0..self.height {
+                for k in 0..self.depth {
+                    cells.push(Point3::new(i, j, k));
+                }
+            }
+        }
+        cells
+    }
+}
+
+// 8x8x8, since that should be a good simulation of a real 3d LED cube one could buy.
+impl Default for Grid {
+    fn default() -> Grid {
+        Grid::new(8, 8, 8)
+    }
+}
+
+// parses dimensions of the form WIDTHxHEIGHTxDEPTH, e.g. 8x8x32
+impl FromStr for Grid {
+    type Err = Box<error::Error>;
+
+    fn from_str(s: &str) -> Result<Grid, Box<error::Error>> {
+        let dims = s
+            .split('x')
+            .map(|d| d.parse::<i32>())
+            .collect::<Result<Vec<i32>, _>>()?;
+        match dims.as_slice() {
+            &[width, height, depth] if width > 0 && height > 0 && depth > 0 => {
+                // len counts cells as an i32, so no grid can hold more than that
+                match width.checked_mul(height).and_then(|n| n.checked_mul(depth)) {
+                    Some(_) => Ok(Grid::new(width, height, depth)),
+                    None => Err(From::from(format!(
+                        "grid dimensions {:?} have more than {} cells",
+                        s,
+                        i32::max_value()
+                    ))),
+                }
+            }
+            _ => Err(From::from(format!(
+                "grid dimensions {:?} are not of the form WIDTHxHEIGHTxDEPTH",
+                s
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        for &(s, width, height, depth) in &[
+            ("8x8x8", 8, 8, 8),
+            ("1x2x3", 1, 2, 3),
+            ("16x16x32", 16, 16, 32),
+            ("46340x46340x1", 46340, 46340, 1),
+        ] {
+            assert_eq!(
+                s.parse::<Grid>().ok(),
+                Some(Grid::new(width, height, depth))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        for s in &[
+            "",
+            "8",
+            "8x8",
+            "8x8x8x8",
+            "8X8X8",
+            "axbxc",
+            "8x8x",
+            "0x8x8",
+            "8x0x8",
+            "8x8x-1",
+            "65536x65536x1",
+            "2147483647x2x1",
+            "46341x46341x1",
+        ] {
+            assert!(s.parse::<Grid>().is_err(), "{:?} parsed", s);
+        }
+    }
+}
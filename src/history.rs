@@ -0,0 +1,177 @@
+use palette::LinSrgba;
+use std::collections::VecDeque;
+
+// how many changed voxels are remembered for undo by default, across every operation.
+// the oldest operations are forgotten first.
+pub static DEFAULT_LIMIT: usize = 1 << 20;
+
+// one voxel a command changed: its index in its layer's buffer, and its color before and
+// after
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Change {
+    pub index: usize,
+    pub before: LinSrgba<f32>,
+    pub after: LinSrgba<f32>,
+}
+
+// every voxel one command changed on one layer, in the order they were painted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub layer: String,
+    pub changes: Vec<Change>,
+}
+
+// a bounded record of applied operations (each the edits of one command), to undo and redo
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    size: usize,  // changes held in both stacks
+    limit: usize, // the most changes held before old operations are forgotten
+}
+
+fn size(op: &[Edit]) -> usize {
+    op.iter().map(|e| e.changes.len()).sum()
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: vec![],
+            size: 0,
+            limit,
+        }
+    }
+
+    // remembers $op as the latest operation, forgetting anything undone before it. the
+    // latest operation is always kept, even if it alone is over the limit.
+    pub fn record(&mut self, op: Vec<Edit>) {
+        if size(&op) == 0 {
+            return;
+        }
+        for undone in self.redo.drain(..) {
+            self.size -= size(&undone);
+        }
+        self.size += size(&op);
+        self.undo.push_back(op);
+        while self.size > self.limit && self.undo.len() > 1 {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.size -= size(&oldest);
+            }
+        }
+    }
+
+    // takes the latest operation off the undo stack, returning it so its changes can be
+    // reverted
+    pub fn undo(&mut self) -> Option<&[Edit]> {
+        let op = self.undo.pop_back()?;
+        self.redo.push(op);
+        self.redo.last().map(|op| &op[..])
+    }
+
+    // takes the latest undone operation back onto the undo stack, returning it so its
+    // changes can be reapplied
+    pub fn redo(&mut self) -> Option<&[Edit]> {
+        let op = self.redo.pop()?;
+        self.undo.push_back(op);
+        self.undo.back().map(|op| &op[..])
+    }
+
+    // forgets every change made to the layer called $layer, once it's been reset or removed,
+    // along with any operation left with nothing to undo
+    pub fn forget(&mut self, layer: &str) {
+        for op in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            op.retain(|e| e.layer != layer);
+        }
+        self.undo.retain(|op| !op.is_empty());
+        self.redo.retain(|op| !op.is_empty());
+        self.size = self.undo.iter().chain(&self.redo).map(|op| size(op)).sum();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.size = 0;
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new(DEFAULT_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an operation changing the first $n voxels of $layer, tagged with $tag so operations
+    // can be told apart
+    fn op(layer: &str, n: usize, tag: f32) -> Vec<Edit> {
+        let changes = (0..n)
+            .map(|index| Change {
+                index,
+                before: LinSrgba::new(0.0, 0.0, 0.0, 0.0),
+                after: LinSrgba::new(tag, 0.0, 0.0, 1.0),
+            })
+            .collect();
+        vec![Edit {
+            layer: layer.to_string(),
+            changes,
+        }]
+    }
+
+    #[test]
+    fn the_limit_forgets_the_oldest_operations_but_never_the_latest() {
+        let mut history = History::new(10);
+        history.record(op("base", 4, 1.0));
+        history.record(op("base", 4, 2.0));
+        assert_eq!(history.size, 8);
+        history.record(op("base", 4, 3.0));
+        assert_eq!(history.size, 8);
+        assert_eq!(history.undo(), Some(&op("base", 4, 3.0)[..]));
+        assert_eq!(history.undo(), Some(&op("base", 4, 2.0)[..]));
+        assert_eq!(history.undo(), None);
+
+        history.record(op("base", 25, 4.0));
+        assert_eq!(history.size, 25);
+        assert_eq!(history.undo(), Some(&op("base", 25, 4.0)[..]));
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn recording_forgets_what_was_undone() {
+        let mut history = History::new(100);
+        history.record(op("base", 2, 1.0));
+        history.record(op("base", 2, 2.0));
+        history.undo();
+        assert_eq!(history.size, 4);
+        history.record(op("base", 3, 3.0));
+        assert_eq!(history.size, 5);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(&op("base", 3, 3.0)[..]));
+        assert_eq!(history.redo(), Some(&op("base", 3, 3.0)[..]));
+
+        // nothing changed, so nothing to forget
+        history.undo();
+        history.record(vec![]);
+        assert_eq!(history.redo(), Some(&op("base", 3, 3.0)[..]));
+    }
+
+    #[test]
+    fn forgetting_a_layer_drops_its_edits_and_empty_operations() {
+        let mut history = History::new(100);
+        history.record(op("fx", 2, 1.0));
+        let mut both = op("base", 3, 2.0);
+        both.extend(op("fx", 4, 2.0));
+        history.record(both);
+        history.record(op("fx", 5, 3.0));
+        history.undo();
+        history.forget("fx");
+        assert_eq!(history.size, 3);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(&op("base", 3, 2.0)[..]));
+        assert_eq!(history.undo(), None);
+    }
+}
@@ -0,0 +1,183 @@
+// a directory the previewer keeps its state in as it runs, so that after a crash or restart
+// it can pick up where it left off. it holds:
+//
+//   state.voxels        the composited layers as of the latest checkpoint, as a snapshot
+//                       file
+//   commands.osc        optionally, every command received since the latest checkpoint,
+//                       oldest first. each batch of commands applied together is an osc
+//                       bundle timetagged with when it was applied, preceded by its size in
+//                       bytes as a big-endian int32 (as over tcp with length framing).
+//   commands.prev.osc   commands.osc as the previous run left it, so that what a crash kept
+//                       out of the checkpoint survives a restart
+
+use bundle;
+use command::Command;
+use error::Error;
+use framing::Framing;
+use layer;
+use rosc;
+use rosc::{OscBundle, OscPacket};
+use snapshot;
+use state::State;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use voxel_buffer::VoxelBuffer;
+
+pub static STATE_FILE: &'static str = "state.voxels";
+pub static COMMANDS_FILE: &'static str = "commands.osc";
+pub static PREVIOUS_COMMANDS_FILE: &'static str = "commands.prev.osc";
+
+pub struct Journal {
+    dir: PathBuf,
+    interval: Duration, // the least time between checkpoints
+    saved: Instant,     // when the latest checkpoint was taken
+    dirty: bool,        // whether any commands were applied since then
+    commands: Option<fs::File>,
+}
+
+fn file_error(path: &Path, e: &io::Error) -> Error {
+    Error::File {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    }
+}
+
+impl Journal {
+    // starts journaling into $dir, making it if need be, with checkpoints at most every
+    // $interval. with $log_commands, received commands are logged there too, and any the
+    // previous run logged are kept aside.
+    pub fn open(dir: &Path, interval: Duration, log_commands: bool) -> Result<Journal, Error> {
+        fs::create_dir_all(dir).map_err(|e| file_error(dir, &e))?;
+        let commands = if log_commands {
+            let path = dir.join(COMMANDS_FILE);
+            if fs::metadata(&path)
+                .map(|meta| meta.len() > 0)
+                .unwrap_or(false)
+            {
+                fs::rename(&path, dir.join(PREVIOUS_COMMANDS_FILE))
+                    .map_err(|e| file_error(&path, &e))?;
+            }
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| file_error(&path, &e))?;
+            Some(file)
+        } else {
+            None
+        };
+        Ok(Journal {
+            dir: dir.to_path_buf(),
+            interval,
+            saved: Instant::now(),
+            dirty: false,
+            commands,
+        })
+    }
+
+    // notes that $cmds are about to be applied together, logging them if commands are
+    // being logged
+    pub fn record(&mut self, cmds: &[Command]) -> Result<(), Error> {
+        if cmds.is_empty() {
+            return Ok(());
+        }
+        self.dirty = true;
+        let path = self.dir.join(COMMANDS_FILE);
+        let file = match self.commands {
+            Some(ref mut file) => file,
+            None => return Ok(()),
+        };
+        let packet = OscPacket::Bundle(OscBundle {
+            timetag: bundle::to_timetag(SystemTime::now()),
+            content: cmds
+                .iter()
+                .map(|cmd| OscPacket::Message(cmd.to_osc()))
+                .collect(),
+        });
+        let bytes = rosc::encoder::encode(&packet).map_err(|e| Error::File {
+            path: path.display().to_string(),
+            reason: format!("couldn't encode commands: {:?}", e),
+        })?;
+        file.write_all(&Framing::LengthPrefixed.encode(&bytes))
+            .map_err(|e| file_error(&path, &e))
+    }
+
+    // takes a checkpoint of $state if anything has changed and the interval has passed
+    // since the latest one
+    pub fn tick(&mut self, state: &State) -> Result<(), Error> {
+        if self.dirty && self.saved.elapsed() >= self.interval {
+            self.checkpoint(state)
+        } else {
+            Ok(())
+        }
+    }
+
+    // saves $state now, and then empties the command log, since the commands in it are
+    // part of the checkpoint. if that fails, the next tick tries again once the interval
+    // has passed.
+    pub fn checkpoint(&mut self, state: &State) -> Result<(), Error> {
+        self.saved = Instant::now();
+        let frame = layer::flatten(&state.layers, state.grid());
+        snapshot::save(&self.dir.join(STATE_FILE), &frame)?;
+        self.dirty = false;
+        // appends go to the new end of the file, so it starts again from nothing
+        if let Some(ref file) = self.commands {
+            file.set_len(0)
+                .map_err(|e| file_error(&self.dir.join(COMMANDS_FILE), &e))?;
+        }
+        Ok(())
+    }
+}
+
+// the state saved by the latest checkpoint in the journal at $dir, or None if there's none
+pub fn restore(dir: &Path) -> Result<Option<VoxelBuffer>, Error> {
+    let path = dir.join(STATE_FILE);
+    if path.exists() {
+        snapshot::load(&path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grid::Grid;
+    use std::env;
+    use std::process;
+
+    fn len(path: &Path) -> u64 {
+        fs::metadata(path).unwrap().len()
+    }
+
+    #[test]
+    fn checkpoints_empty_the_command_log() {
+        let dir = env::temp_dir().join(format!("voxel-preview-journal-{}", process::id()));
+        let log = dir.join(COMMANDS_FILE);
+        let mut state = State::new(VoxelBuffer::rainbow(Grid::new(2, 2, 2)));
+        let mut journal = Journal::open(&dir, Duration::from_secs(3600), true).unwrap();
+        assert_eq!(restore(&dir), Ok(None));
+
+        journal.record(&[Command::Clear]).unwrap();
+        state.apply(&Command::Clear).unwrap();
+        assert!(len(&log) > 0);
+        journal.tick(&state).unwrap();
+        assert_eq!(restore(&dir), Ok(None), "checkpointed before the interval");
+
+        journal.checkpoint(&state).unwrap();
+        assert_eq!(len(&log), 0);
+        assert_eq!(restore(&dir), Ok(Some(state.frame())));
+
+        // what a crash left in the log is kept aside by the next run
+        journal.record(&[Command::Blackout]).unwrap();
+        let logged = len(&log);
+        drop(journal);
+        Journal::open(&dir, Duration::from_secs(3600), true).unwrap();
+        assert_eq!(len(&dir.join(PREVIOUS_COMMANDS_FILE)), logged);
+        assert_eq!(len(&log), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
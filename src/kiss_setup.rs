@@ -1,19 +1,22 @@
-use CUBE_WIDTH;
 use EYE_OFFSET;
 use VOX_RADIUS;
 use WINDOW_H;
 use WINDOW_W;
+use glfw::{Action, Key, Modifiers, WindowEvent};
 use kiss3d::camera::ArcBall;
 use kiss3d::light::Light;
 use kiss3d::scene::SceneNode;
 use kiss3d::window::Window;
 use na::Translation3;
 use na::{Point3, Vector3};
+use voxel_preview::command::Command;
+use voxel_preview::grid::Grid;
+use voxel_preview::voxel_buffer::VoxelBuffer;
 
 // creates a window in userland with default lighting
-pub fn make_window() -> Window {
+pub fn make_window(grid: &Grid) -> Window {
     let mut window = Window::new_with_size(
-        &format!("{}x{}x{}", CUBE_WIDTH, CUBE_WIDTH, CUBE_WIDTH),
+        &format!("{}x{}x{}", grid.width, grid.height, grid.depth),
         WINDOW_W,
         WINDOW_H,
     );
@@ -22,21 +25,27 @@ pub fn make_window() -> Window {
 }
 
 // creates a camera fixed on the center of the voxel structure,  with some
-pub fn make_camera() -> ArcBall {
-    let at: Point3<f32> = (((CUBE_WIDTH as f32) / 2.0) - 0.5) * Point3::new(1.0, 1.0, -1.0);
-    let eye: Point3<f32> = at + EYE_OFFSET * (CUBE_WIDTH as f32) * Vector3::new(1.0, 1.0, 1.0);
+pub fn make_camera(grid: &Grid) -> ArcBall {
+    let at: Point3<f32> = Point3::new(
+        ((grid.width as f32) / 2.0) - 0.5,
+        ((grid.depth as f32) / 2.0) - 0.5,
+        -(((grid.height as f32) / 2.0) - 0.5),
+    );
+    let eye: Point3<f32> =
+        at + EYE_OFFSET * (grid.max_extent() as f32) * Vector3::new(1.0, 1.0, 1.0);
     ArcBall::new(eye, at)
 }
 
-// creates CUBE_WIDTH x CUBE_WIDTH x CUBE_WIDTH array of voxels attached to window,
-// and returns the 3d array of scenenodes for later mutation
-pub fn make_cube_in_window(window: &mut Window) -> Vec<Vec<Vec<SceneNode>>> {
+// creates a width x height x depth array of voxels attached to window, spanning the grid
+// of $buffer and colored from it, and returns the 3d array of scenenodes for later syncing
+pub fn make_cube_in_window(window: &mut Window, buffer: &VoxelBuffer) -> Vec<Vec<Vec<SceneNode>>> {
+    let grid = buffer.grid();
     let mut voxels = Vec::new();
-    for i in 0..CUBE_WIDTH {
+    for i in 0..grid.width {
         voxels.push(Vec::new());
-        for j in 0..CUBE_WIDTH {
+        for j in 0..grid.height {
             voxels[i as usize].push(Vec::new());
-            for k in 0..CUBE_WIDTH {
+            for k in 0..grid.depth {
                 let mut vox = window.add_sphere(VOX_RADIUS);
 
                 vox.append_translation(&Translation3::new(
@@ -45,20 +54,49 @@ pub fn make_cube_in_window(window: &mut Window) -> Vec<Vec<Vec<SceneNode>>> {
                     (-1.0) * (j as f32),
                 ));
 
-                // default rainbow coloring
-                vox.set_color(
-                    (i as f32 / CUBE_WIDTH as f32),
-                    (j as f32 / CUBE_WIDTH as f32),
-                    (k as f32 / CUBE_WIDTH as f32),
-                );
-
                 voxels[i as usize][j as usize].push(vox);
             }
         }
     }
+    sync_cube(&mut voxels, buffer);
     voxels
 }
 
+// copies every cell of $buffer onto the matching scenenode. kiss3d can't draw translucent
+// geometry, so a voxel's alpha is shown by dimming it towards black instead.
+pub fn sync_cube(voxels: &mut Vec<Vec<Vec<SceneNode>>>, buffer: &VoxelBuffer) {
+    for cell in buffer.grid().cells() {
+        if let Ok(clr) = buffer.get(cell) {
+            voxels[cell.x as usize][cell.y as usize][cell.z as usize].set_color(
+                clr.red * clr.alpha,
+                clr.green * clr.alpha,
+                clr.blue * clr.alpha,
+            );
+        }
+    }
+}
+
+// the commands for any keyboard shortcuts pressed since the last frame: ctrl+z to undo, and
+// ctrl+shift+z or ctrl+y to redo (cmd rather than ctrl on a mac)
+pub fn shortcuts(window: &Window) -> Vec<Command> {
+    let mut cmds = vec![];
+    for mut event in window.events().iter() {
+        if let WindowEvent::Key(key, _, Action::Press, mods) = event.value {
+            if !mods.intersects(Modifiers::Control | Modifiers::Super) {
+                continue;
+            }
+            match key {
+                Key::Z if mods.contains(Modifiers::Shift) => cmds.push(Command::Redo),
+                Key::Z => cmds.push(Command::Undo),
+                Key::Y => cmds.push(Command::Redo),
+                _ => continue,
+            }
+            event.inhibited = true;
+        }
+    }
+    cmds
+}
+
 pub fn make_axes(window: &mut Window) {
     let _ = window.draw_line(
         &Point3::origin(),
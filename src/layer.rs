@@ -0,0 +1,396 @@
+use blend::BlendMode;
+use command::Command;
+use error::Error;
+use grid::Grid;
+use history::Change;
+use na::{Point3, Vector3};
+use paint;
+use palette::LinSrgba;
+use raw::{RawFormat, Sample};
+use rosc::{OscMessage, OscType};
+use voxel_buffer::VoxelBuffer;
+use writers;
+
+// one of the stack of voxel grids the cube is composited from. each is drawn into on its
+// own, so what one client draws on one layer can't destroy what another draws on another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub buffer: VoxelBuffer,
+    pub mode: BlendMode, // how drawing commands are blended into this layer
+    pub opacity: f32,
+    pub composite: BlendMode, // how this layer is blended onto the layers below it
+    pub visible: bool,
+    pub z: i32,           // layers with a higher z are composited on top
+    changes: Vec<Change>, // voxels changed since take_changes was last called
+}
+
+impl Layer {
+    // a visible, fully opaque layer holding $buffer
+    pub fn new(name: &str, buffer: VoxelBuffer) -> Layer {
+        Layer {
+            name: name.to_string(),
+            buffer,
+            mode: BlendMode::default(),
+            opacity: 1.0,
+            composite: BlendMode::Over,
+            visible: true,
+            z: 0,
+            changes: vec![],
+        }
+    }
+
+    // the voxels changed since this was last called, for the undo history
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        self.changes.drain(..).collect()
+    }
+
+    // applies $cmd to this layer alone, returning any replies meant for its sender. a
+    // drawing command still paints every cell it can when some of its cells are out of
+    // range, and then reports the first of those.
+    pub fn apply(&mut self, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+        match *cmd {
+            Command::Blend { mode } => self.mode = mode,
+            Command::GetVoxel { .. } | Command::GetRegion { .. } | Command::GetFrame => {
+                return query(&self.buffer, cmd);
+            }
+            Command::Clear => {
+                let len = self.buffer.grid().len();
+                self.replace_cells(&vec![LinSrgba::new(0.0, 0.0, 0.0, 0.0); len]);
+            }
+            Command::FrameRaw {
+                ref format,
+                ref data,
+            } => {
+                let len = self.buffer.grid().len();
+                match format.decode(data, len) {
+                    Some(cells) => self.replace_cells(&cells),
+                    None => {
+                        return Err(Error::InvalidValue {
+                            index: 1,
+                            name: "data",
+                            value: format!("{} bytes, not {}", data.len(), len * format.stride()),
+                        });
+                    }
+                }
+            }
+            Command::Opacity { opacity } => self.opacity = opacity,
+            Command::Composite { mode } => self.composite = mode,
+            Command::Visible { visible } => self.visible = visible,
+            Command::ZOrder { z } => self.z = z,
+            _ => self.draw(cmd)?,
+        }
+        Ok(vec![])
+    }
+
+    // paints $clr onto the voxel at $pt, remembering its old color
+    fn paint(&mut self, pt: Point3<i32>, clr: LinSrgba<f32>) -> Result<(), Error> {
+        let index = self.buffer.grid().index(pt)?;
+        let before = self.buffer.cells()[index];
+        paint::paint(&mut self.buffer, pt, clr, self.mode)?;
+        let after = self.buffer.cells()[index];
+        if after != before {
+            self.changes.push(Change {
+                index,
+                before,
+                after,
+            });
+        }
+        Ok(())
+    }
+
+    // sets every voxel to the matching color in $cells, remembering the old colors
+    pub fn replace_cells(&mut self, cells: &[LinSrgba<f32>]) {
+        for (index, (old, &new)) in self.buffer.cells_mut().iter_mut().zip(cells).enumerate() {
+            if *old != new {
+                self.changes.push(Change {
+                    index,
+                    before: *old,
+                    after: new,
+                });
+                *old = new;
+            }
+        }
+    }
+
+    fn draw(&mut self, cmd: &Command) -> Result<(), Error> {
+        let grid = self.buffer.grid();
+        let mut first_err = None;
+        if let Some((shape, shader)) = cmd.shape_and_shader(&grid) {
+            for cell in shape {
+                if let Err(e) = self.paint(cell, shader(cell)) {
+                    first_err = first_err.or(Some(e));
+                }
+            }
+        } else if let Command::VoxelsColored { ref voxels } = *cmd {
+            for &(pt, clr) in voxels {
+                if let Err(e) = self.paint(pt, clr) {
+                    first_err = first_err.or(Some(e));
+                }
+            }
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+// the reply to $cmd if it is a query about the voxels in $buffer, or no replies if it isn't
+pub fn query(buffer: &VoxelBuffer, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+    match *cmd {
+        Command::GetVoxel { pt } => Ok(vec![voxel_reply(buffer, pt)?]),
+        Command::GetRegion { pt, size } => Ok(vec![region_reply(buffer, pt, size)?]),
+        Command::GetFrame => Ok(vec![frame_reply(buffer)]),
+        _ => Ok(vec![]),
+    }
+}
+
+// /voxel x y z r g b a
+fn voxel_reply(buffer: &VoxelBuffer, pt: Point3<i32>) -> Result<OscMessage, Error> {
+    let mut args = vec![];
+    writers::dsc_point_3(&mut args, pt);
+    writers::lin_srgba(&mut args, buffer.get(pt)?);
+    Ok(OscMessage {
+        addr: "/voxel".to_string(),
+        args: Some(args),
+    })
+}
+
+// /region x y z i j k <blob>, with the blob holding the colors of every cell from (x, y, z)
+// up to but excluding (x+i, y+j, z+k). the whole box is checked against the grid before any
+// of it is read, since a huge size would overflow or ask for far more cells than exist.
+fn region_reply(
+    buffer: &VoxelBuffer,
+    pt: Point3<i32>,
+    size: Vector3<i32>,
+) -> Result<OscMessage, Error> {
+    let axes = [
+        (pt.x, size.x, "i"),
+        (pt.y, size.y, "j"),
+        (pt.z, size.z, "k"),
+    ];
+    let mut end = [0; 3];
+    for (n, &(from, len, name)) in axes.iter().enumerate() {
+        end[n] = match from.checked_add(len) {
+            Some(end) if len >= 0 => end,
+            _ => {
+                return Err(Error::InvalidValue {
+                    index: 3 + n,
+                    name,
+                    value: len.to_string(),
+                })
+            }
+        };
+    }
+    if size.x > 0 && size.y > 0 && size.z > 0 {
+        let grid = buffer.grid();
+        grid.index(pt)?;
+        grid.index(Point3::new(end[0] - 1, end[1] - 1, end[2] - 1))?;
+    }
+
+    let mut blob = vec![];
+    for i in pt.x..end[0] {
+        for j in pt.y..end[1] {
+            for k in pt.z..end[2] {
+                writers::rgba_bytes(&mut blob, buffer.get(Point3::new(i, j, k))?);
+            }
+        }
+    }
+    let mut args = vec![];
+    writers::dsc_point_3(&mut args, pt);
+    writers::dsc_vector_3(&mut args, size);
+    args.push(OscType::Blob(blob));
+    Ok(OscMessage {
+        addr: "/region".to_string(),
+        args: Some(args),
+    })
+}
+
+// /frame width height depth rgba_u8 <blob>, with the blob holding the colors of every cell
+// as /frame/raw would take them. four bytes a cell keep the frame of a 16x16x16 cube within
+// one datagram.
+fn frame_reply(buffer: &VoxelBuffer) -> OscMessage {
+    let grid = buffer.grid();
+    let format = RawFormat {
+        order: "rgba".to_string(),
+        sample: Sample::U8,
+    };
+    OscMessage {
+        addr: "/frame".to_string(),
+        args: Some(vec![
+            OscType::Int(grid.width),
+            OscType::Int(grid.height),
+            OscType::Int(grid.depth),
+            OscType::String(format.name()),
+            OscType::Blob(format.encode(buffer.cells())),
+        ]),
+    }
+}
+
+// composites the visible layers in $layers over $grid, from the lowest z up. layers with
+// the same z are stacked in the order given.
+pub fn flatten(layers: &[Layer], grid: Grid) -> VoxelBuffer {
+    let mut stack: Vec<&Layer> = layers.iter().filter(|l| l.visible).collect();
+    stack.sort_by_key(|l| l.z);
+
+    let mut out = VoxelBuffer::new(grid, LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+    for layer in stack {
+        for (dst, src) in out.cells_mut().iter_mut().zip(layer.buffer.cells()) {
+            let mut src = *src;
+            src.alpha *= layer.opacity;
+            // a transparent cell leaves what's below it alone in every mode but replace, so
+            // it's skipped rather than sending what's below through a blend for nothing
+            if src.alpha == 0.0 && layer.composite != BlendMode::Replace {
+                continue;
+            }
+            *dst = layer.composite.apply(*dst, src);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(pt: (i32, i32, i32), size: (i32, i32, i32)) -> Result<Vec<OscMessage>, Error> {
+        let buffer = VoxelBuffer::rainbow(Grid::new(2, 3, 4));
+        query(
+            &buffer,
+            &Command::GetRegion {
+                pt: Point3::new(pt.0, pt.1, pt.2),
+                size: Vector3::new(size.0, size.1, size.2),
+            },
+        )
+    }
+
+    fn solid(name: &str, clr: LinSrgba<f32>) -> Layer {
+        Layer::new(name, VoxelBuffer::new(Grid::new(1, 1, 2), clr))
+    }
+
+    // the color flattening $layers shows in the first cell
+    fn shown(layers: &[Layer]) -> LinSrgba<f32> {
+        flatten(layers, Grid::new(1, 1, 2)).cells()[0]
+    }
+
+    fn blob_len(replies: Vec<OscMessage>) -> usize {
+        match replies[0].args.as_ref().unwrap()[6] {
+            OscType::Blob(ref blob) => blob.len(),
+            ref arg => panic!("{:?} isn't a blob", arg),
+        }
+    }
+
+    #[test]
+    fn regions_within_the_grid_are_read() {
+        assert_eq!(
+            blob_len(region((0, 0, 0), (2, 3, 4)).unwrap()),
+            2 * 3 * 4 * 16
+        );
+        assert_eq!(blob_len(region((1, 1, 1), (1, 2, 3)).unwrap()), 6 * 16);
+        assert_eq!(blob_len(region((1, 1, 1), (0, 2, 3)).unwrap()), 0);
+    }
+
+    #[test]
+    fn regions_off_the_edge_of_the_grid_are_out_of_bounds() {
+        let grid = Grid::new(2, 3, 4);
+        assert_eq!(
+            region((1, 1, 1), (2, 1, 1)),
+            Err(Error::OutOfBounds {
+                pt: Point3::new(2, 1, 1),
+                grid,
+            })
+        );
+        assert_eq!(
+            region((-1, 0, 0), (2, 1, 1)),
+            Err(Error::OutOfBounds {
+                pt: Point3::new(-1, 0, 0),
+                grid,
+            })
+        );
+    }
+
+    #[test]
+    fn regions_with_overflowing_or_negative_sizes_are_invalid() {
+        assert_eq!(
+            region((1, 0, 0), (i32::max_value(), 1, 1)),
+            Err(Error::InvalidValue {
+                index: 3,
+                name: "i",
+                value: i32::max_value().to_string(),
+            })
+        );
+        assert_eq!(
+            region((0, 0, 1), (1, 1, i32::max_value())),
+            Err(Error::InvalidValue {
+                index: 5,
+                name: "k",
+                value: i32::max_value().to_string(),
+            })
+        );
+        assert_eq!(
+            region((0, 0, 0), (1, -1, 1)),
+            Err(Error::InvalidValue {
+                index: 4,
+                name: "j",
+                value: "-1".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn opacity_scales_alpha() {
+        let mut fx = solid("fx", LinSrgba::new(1.0, 0.0, 0.0, 1.0));
+        fx.opacity = 0.5;
+        assert_eq!(shown(&[fx.clone()]), LinSrgba::new(1.0, 0.0, 0.0, 0.5));
+        fx.composite = BlendMode::Replace;
+        fx.opacity = 0.25;
+        assert_eq!(shown(&[fx]), LinSrgba::new(1.0, 0.0, 0.0, 0.25));
+    }
+
+    #[test]
+    fn higher_layers_are_drawn_on_top_with_ties_in_order() {
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let blue = LinSrgba::new(0.0, 0.0, 1.0, 1.0);
+        let green = LinSrgba::new(0.0, 1.0, 0.0, 1.0);
+        let mut layers = vec![solid("base", green), solid("a", red), solid("b", blue)];
+        assert_eq!(shown(&layers), blue);
+        layers[1].z = 1;
+        assert_eq!(shown(&layers), red);
+        layers[2].z = 1;
+        assert_eq!(shown(&layers), blue);
+        layers[1].z = -1;
+        layers[2].z = -1;
+        assert_eq!(shown(&layers), green);
+    }
+
+    #[test]
+    fn hidden_layers_are_skipped() {
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let blue = LinSrgba::new(0.0, 0.0, 1.0, 1.0);
+        let mut layers = vec![solid("base", red), solid("fx", blue)];
+        layers[1].visible = false;
+        assert_eq!(shown(&layers), red);
+        layers[0].visible = false;
+        assert_eq!(shown(&layers), LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn transparent_cells_clear_what_is_below_only_when_replacing() {
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let clear = LinSrgba::new(0.0, 0.0, 0.0, 0.0);
+        let mut layers = vec![solid("base", red), solid("fx", clear)];
+        for &mode in &[BlendMode::Over, BlendMode::Add, BlendMode::Xor] {
+            layers[1].composite = mode;
+            assert_eq!(shown(&layers), red);
+        }
+        layers[1].composite = BlendMode::Replace;
+        assert_eq!(shown(&layers), clear);
+
+        // an opaque cell made transparent by the layer's opacity clears it too
+        layers[1] = solid("fx", LinSrgba::new(0.0, 0.0, 1.0, 1.0));
+        layers[1].composite = BlendMode::Replace;
+        layers[1].opacity = 0.0;
+        assert_eq!(shown(&layers), LinSrgba::new(0.0, 0.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,31 @@
+#![feature(inclusive_range, inclusive_range_syntax)]
+#![feature(match_default_bindings)]
+#![feature(range_contains)]
+#![feature(slice_patterns)]
+#![feature(underscore_lifetimes)]
+
+extern crate nalgebra as na;
+extern crate palette;
+extern crate rosc;
+pub mod blend;
+pub mod bresenham3d;
+pub mod bundle;
+pub mod command;
+pub mod error;
+pub mod framing;
+pub mod geometry;
+pub mod grid;
+pub mod history;
+pub mod journal;
+pub mod layer;
+pub mod paint;
+pub mod pattern;
+pub mod raw;
+pub mod readers;
+pub mod registry;
+pub mod scheduler;
+pub mod shaders;
+pub mod snapshot;
+pub mod state;
+pub mod voxel_buffer;
+pub mod writers;
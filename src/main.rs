@@ -1,337 +1,187 @@
-#![feature(inclusive_range, inclusive_range_syntax)]
-#![feature(match_default_bindings)]
-#![feature(range_contains)]
-#![feature(slice_patterns)]
-#![feature(underscore_lifetimes)]
-
+extern crate glfw;
 extern crate kiss3d;
 extern crate nalgebra as na;
-extern crate palette;
+extern crate net2;
 extern crate rosc;
 extern crate simple_error;
-mod bresenham3d;
-mod geometry;
+extern crate voxel_preview;
+mod config;
 mod kiss_setup;
-mod paint;
-mod readers;
+mod net;
 
-use na::Point3;
-use palette::LinSrgba;
-use palette::gradient::Gradient;
-use rosc::{OscMessage, OscPacket, OscType};
+use config::{Config, Endpoint};
 use std::env;
-use std::error::Error;
-use std::io;
-use std::net::{SocketAddrV4, UdpSocket};
-use std::slice;
-use std::str::FromStr;
-use std::time::Duration;
+use std::net::{TcpListener, UdpSocket};
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+use voxel_preview::journal;
+use voxel_preview::journal::Journal;
+use voxel_preview::scheduler::Scheduler;
+use voxel_preview::snapshot;
+use voxel_preview::state::State;
+use voxel_preview::voxel_buffer::VoxelBuffer;
 
 // kiss3d constants
 static WINDOW_W: u32 = 888; // arbitrary
 static WINDOW_H: u32 = 888;
 static ROTATION_RAD: f32 = 0.000; // amount by which to rotate the camera yaw per frame
                                   //static ROTATION_RAD: f32 = 0.0; // amount by which to rotate the camera yaw per frame
-static CUBE_WIDTH: i32 = 8; // this will probably always be 8, since it should be a good
-                            // simulation of a real 3d LED cube one could buy.
 static VOX_RADIUS: f32 = 0.05; // radius of a voxel 'sphere'. this needs to be small,
                                // since kiss3d officially doesn't support transparency
 static EYE_OFFSET: f32 = 1.5; // arbitrary
-static POLL_TIMEOUT: u64 = 10; // polling is efficient enough to support this
 
-type Shape = Vec<Point3<i32>>;
-type Shader = Fn(Point3<i32>) -> LinSrgba<f32>;
+// network constants
+static POLL_TIMEOUT: u64 = 10; // how often the network thread checks for shutdown, in ms
+static QUEUE_SIZE: usize = 1024; // batches of commands waiting for the render loop
+static MAX_DATAGRAM: usize = 65536; // the largest datagram read; no udp payload is larger
+static WRITE_TIMEOUT: u64 = 250; // how long a reply waits for a tcp client to read, in ms
+static MAX_LOGGED_ARGS: usize = 16; // messages with more arguments are logged without them
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let usage = format!("Usage: {} CLIENT_IP:CLIENT_PORT", &args[0]);
-    if args.len() < 2 {
-        panic!(usage);
+    let config = match Config::from_args(&args[1..]) {
+        Ok(config) => config,
+        Err(e) => panic!("{}\n{}", e, Config::usage(&args[0])),
+    };
+    let mut listener = net::Listener::new(config.coercion, config.error_replies);
+    for endpoint in &config.listen {
+        let listening = match *endpoint {
+            Endpoint::Udp(addr) => UdpSocket::bind(addr).and_then(|s| listener.listen_udp(s)),
+            Endpoint::Multicast(group) => {
+                net::bind_multicast(group, &config.interface).and_then(|s| listener.listen_udp(s))
+            }
+            Endpoint::Unix(ref path) => listener.listen_unix(path),
+        };
+        if let Err(e) = listening {
+            panic!("Couldn't listen on {}: {}", endpoint, e);
+        }
+    }
+    for &addr in &config.tcp {
+        let listening =
+            TcpListener::bind(addr).and_then(|l| listener.listen_tcp(l, config.framing));
+        if let Err(e) = listening {
+            panic!("Couldn't listen on {} (tcp): {}", addr, e);
+        }
     }
-    let addr = SocketAddrV4::from_str(&args[1]).unwrap();
-    let socket = UdpSocket::bind(addr).unwrap();
-    socket
-        .set_read_timeout(Some(Duration::from_millis(POLL_TIMEOUT)))
-        .unwrap();
-    let mut buf = [0u8; rosc::decoder::MTU];
-    let mut window = kiss_setup::make_window();
-
-    let mut voxels = kiss_setup::make_cube_in_window(&mut window); // initial blank slate
-    let mut cam = kiss_setup::make_camera();
 
-    loop {
+    // the journal's latest checkpoint wins over --load, so a restarted install comes back
+    // as it was. either brings its own size, overriding --size.
+    let restored = match config.journal {
+        Some(ref dir) if config.restore => match journal::restore(dir) {
+            Ok(restored) => restored,
+            Err(e) => panic!("{}", e),
+        },
+        _ => None,
+    };
+    let buffer = match (restored, &config.load) {
+        (Some(buffer), _) => buffer,
+        (None, &Some(ref path)) => match snapshot::load(path) {
+            Ok(buffer) => buffer,
+            Err(e) => panic!("{}", e),
+        },
+        (None, &None) => VoxelBuffer::rainbow(config.grid), // initial blank slate
+    };
+    let grid = buffer.grid();
+    let mut window = kiss_setup::make_window(&grid);
+
+    let mut state = State::new(buffer);
+    state.snapshot_dir = config.snapshot_dir.clone();
+    let mut voxels = kiss_setup::make_cube_in_window(&mut window, &state.frame());
+    let mut cam = kiss_setup::make_camera(&grid);
+    let mut journal = config.journal.as_ref().map(|dir| {
+        match Journal::open(dir, config.journal_interval, config.journal_commands) {
+            Ok(journal) => journal,
+            Err(e) => panic!("{}", e),
+        }
+    });
+    let mut scheduler = Scheduler::default();
+    let mut pending = vec![];
+    let mut dropped = 0;
+    let mut dropped_scheduled = 0;
+
+    while window.render_with_camera(&mut cam) {
         {
-            let _ = window.render_with_camera(&mut cam);
             let curr = cam.yaw();
             cam.set_yaw(curr + ROTATION_RAD);
         }
 
         kiss_setup::make_axes(&mut window);
 
-        match socket.recv_from(&mut buf) {
-            Ok((size, addr_from)) => {
-                println!("Received packet with size {} from: {}", size, addr_from);
-                match rosc::decoder::decode(&buf[..size]) {
-                    Ok(OscPacket::Message(OscMessage {
-                        addr,
-                        args: Some(args),
-                    })) => {
-                        println!("{:?}\t{:?}", addr, args);
-                        let (shape, shader): (Shape, Box<Shader>) =
-                            match get_shape_and_shader(addr, args) {
-                                Ok(load) => load,
-                                Err(e) => {
-                                    println!("{:?}", e);
-                                    continue;
-                                }
-                            };
-                        for cell in shape {
-                            match paint::paint(&mut voxels, cell, shader(cell)) {
-                                Ok(()) => (),
-                                Err(e) => println!("{:?}", e),
-                            }
-                        }
-                    }
-                    Ok(msg) => println!("Recieved other message: {:?}", msg),
-                    Err(e) => println!("Couldn't decode message: {:?}", e),
-                };
-            }
-            Err(e) => {
-                if e.kind() != io::ErrorKind::WouldBlock {
-                    println!("Error receiving from socket: {:?}", e);
-                }
+        let shortcuts = kiss_setup::shortcuts(&window);
+        if let Some(ref mut journal) = journal {
+            if let Err(e) = journal.record(&shortcuts) {
+                println!("{}", e);
             }
         }
-    }
-}
-
-// parses $args and applies the mutation to $voxels
-fn get_shape_and_shader(
-    addr: String,
-    args: Vec<OscType>,
-) -> Result<(Shape, Box<Shader>), Box<Error>> {
-    let mut it: slice::Iter<'_, OscType> = args.iter();
-
-    Ok(match addr.as_ref() {
-        "/dsc/voxel" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = vec![pt];
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
-        }
-
-        "/dsc/line" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let dir = readers::dsc_vector_3(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = geometry::discrete_line(pt, dir);
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
-        }
-
-        "/dsc/line/grad" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let dir = readers::dsc_vector_3(&mut it)?;
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shader = move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            };
-
-            (geometry::discrete_line(pt, dir), Box::new(shader))
-        }
-
-        "/dsc/plane" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let vec1 = readers::dsc_vector_3(&mut it)?;
-            let vec2 = readers::dsc_vector_3(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shader = move |_cell: Point3<i32>| clr;
-
-            (geometry::discrete_plane(pt, vec1, vec2), Box::new(shader))
-        }
-
-        "/dsc/plane/grad" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let vec1 = readers::dsc_vector_3(&mut it)?;
-            let vec2 = readers::dsc_vector_3(&mut it)?;
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shader = move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            };
-
-            (geometry::discrete_plane(pt, vec1, vec2), Box::new(shader))
-        }
-
-        "/dsc/frame" => {
-            let anchor = readers::dsc_point_3(&mut it)?;
-            let vec1 = readers::dsc_vector_3(&mut it)?;
-            let vec2 = readers::dsc_vector_3(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = geometry::discrete_frame(anchor, vec1, vec2);
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
-        }
-
-        "/dsc/frame/grad" => {
-            let anchor = readers::dsc_point_3(&mut it)?;
-            let vec1 = readers::dsc_vector_3(&mut it)?;
-            let vec2 = readers::dsc_vector_3(&mut it)?;
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shape = geometry::discrete_frame(anchor, vec1, vec2);
-            let shader = Box::new(move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            });
-
-            (shape, shader)
-        }
-
-        "/dsc/cuboid" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let vec1 = readers::dsc_vector_3(&mut it)?;
-            let vec2 = readers::dsc_vector_3(&mut it)?;
-            let vec3 = readers::dsc_vector_3(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = geometry::discrete_cuboid(pt, vec1, vec2, vec3);
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
-        }
-
-        "/dsc/cuboid/grad" => {
-            let pt = readers::dsc_point_3(&mut it)?;
-            let vec1 = readers::dsc_vector_3(&mut it)?;
-            let vec2 = readers::dsc_vector_3(&mut it)?;
-            let vec3 = readers::dsc_vector_3(&mut it)?;
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shape = geometry::discrete_cuboid(pt, vec1, vec2, vec3);
-            let shader = Box::new(move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            });
-
-            (shape, shader)
+        for cmd in &shortcuts {
+            if let Err(e) = state.apply(cmd) {
+                println!("{}", e);
+            }
         }
 
-        "/dsc/sphere" => {
-            let center = readers::dsc_point_3(&mut it)?;
-            let p = readers::int(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = geometry::discrete_sphere(center, p);
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
+        let now = SystemTime::now();
+        for envelope in listener.drain() {
+            match envelope.due {
+                Some(due) if due > now => scheduler.push(now, due, envelope),
+                _ => pending.push(envelope),
+            }
         }
-
-        "/dsc/sphere/grad" => {
-            let center = readers::dsc_point_3(&mut it)?;
-            let p = readers::int(&mut it)?;
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shape = geometry::discrete_sphere(center, p);
-            let shader = Box::new(move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            });
-
-            (shape, shader)
+        if listener.stats().dropped.load(Ordering::Relaxed) != dropped {
+            dropped = listener.stats().dropped.load(Ordering::Relaxed);
+            println!("Command queue full: {}", listener.stats());
         }
-
-        "/dsc/shell" => {
-            let center = readers::dsc_point_3(&mut it)?;
-            let p = readers::int(&mut it)?;
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = geometry::discrete_shell(center, p);
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
+        if scheduler.dropped() != dropped_scheduled {
+            dropped_scheduled = scheduler.dropped();
+            println!("Bundle schedule full or too far ahead: {}", scheduler);
         }
 
-        "/dsc/shell/grad" => {
-            let center = readers::dsc_point_3(&mut it)?;
-            let p = readers::int(&mut it)?;
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shape = geometry::discrete_shell(center, p);
-            let shader = Box::new(move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            });
-
-            (shape, shader)
+        // everything due this frame is applied before the cube is synced, so each bundle
+        // shows up all at once
+        pending.extend(scheduler.pop_due(SystemTime::now()));
+        if !pending.is_empty() || !shortcuts.is_empty() {
+            for envelope in pending.drain(..) {
+                if let Some(ref mut journal) = journal {
+                    if let Err(e) = journal.record(&envelope.cmds) {
+                        println!("{}", e);
+                    }
+                }
+                for cmd in envelope.cmds {
+                    match state.apply(&cmd) {
+                        Ok(replies) => {
+                            for reply in replies {
+                                envelope.from.send(reply);
+                            }
+                        }
+                        Err(e) => {
+                            println!("{}", e);
+                            if config.error_replies {
+                                envelope.from.send(e.to_osc(&cmd.osc_addr()));
+                            }
+                        }
+                    }
+                }
+                // a bundle is a frame of its own, even when drawing is double-buffered
+                if envelope.bundled {
+                    state.commit();
+                }
+            }
+            kiss_setup::sync_cube(&mut voxels, &state.frame());
         }
 
-        "/fill/solid" => {
-            let clr = readers::lin_srgba(&mut it)?;
-
-            let shape = geometry::all_cells();
-            let shader = Box::new(move |_cell: Point3<i32>| clr);
-
-            (shape, shader)
+        if let Some(ref mut journal) = journal {
+            if let Err(e) = journal.tick(&state) {
+                println!("{}", e);
+            }
         }
+    }
 
-        "/fill/solid/grad" => {
-            let clr1 = readers::lin_srgba(&mut it)?;
-            let clr2 = readers::lin_srgba(&mut it)?;
-            let clr_pt = readers::dsc_point_3(&mut it)?;
-            let clr_dir = readers::dsc_vector_3(&mut it)?;
-
-            let shape = geometry::all_cells();
-            let shader = Box::new(move |cell: Point3<i32>| {
-                let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
-                let grad = Gradient::new(vec![clr1, clr2]);
-                grad.get(proj)
-            });
-
-            (shape, shader)
+    // one last checkpoint, so a clean exit loses nothing
+    if let Some(ref mut journal) = journal {
+        if let Err(e) = journal.checkpoint(&state) {
+            println!("{}", e);
         }
+    }
 
-        _ => {
-            return Err(From::from(format!(
-                "no match for addr {:?} args {:?}",
-                addr, args
-            )));
-        }
-    })
+    println!("{}", listener.stats());
+    listener.shutdown();
 }
@@ -0,0 +1,516 @@
+use config::Interface;
+use net2::UdpBuilder;
+#[cfg(unix)]
+use net2::unix::UnixUdpBuilderExt;
+use MAX_DATAGRAM;
+use MAX_LOGGED_ARGS;
+use POLL_TIMEOUT;
+use QUEUE_SIZE;
+use WRITE_TIMEOUT;
+use rosc;
+use rosc::{OscMessage, OscPacket, OscType};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+use voxel_preview::bundle;
+use voxel_preview::command::Command;
+use voxel_preview::framing::{Deframer, Framing};
+use voxel_preview::readers::Coercion;
+use voxel_preview::registry::Registry;
+
+// where a packet came from, and so where replies to it go
+#[derive(Clone, Debug)]
+pub enum Peer {
+    Udp(Arc<UdpSocket>, SocketAddr),
+    // the write half of a connection, shared so replies from the render loop and the
+    // connection's own thread don't interleave
+    Tcp(Arc<Mutex<TcpStream>>, SocketAddr, Framing),
+    // the path of the sending socket, which is None if it was never bound to one, and so
+    // can't be replied to
+    #[cfg(unix)]
+    Unix(Arc<UnixDatagram>, Option<PathBuf>),
+}
+
+impl Peer {
+    // encodes $msg and sends it to this peer, logging rather than failing if it can't
+    pub fn send(&self, msg: OscMessage) {
+        let bytes = match rosc::encoder::encode(&OscPacket::Message(msg)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Couldn't encode reply: {:?}", e);
+                return;
+            }
+        };
+        let sent = match *self {
+            Peer::Udp(ref socket, to) => socket.send_to(&bytes, to).map(|_| ()),
+            Peer::Tcp(ref stream, _, framing) => match stream.lock() {
+                Ok(mut stream) => {
+                    let sent = stream.write_all(&framing.encode(&bytes));
+                    // a reply which timed out may be half written, and then nothing after
+                    // it can be framed, so the connection is closed
+                    if sent.is_err() {
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                    sent
+                }
+                Err(_) => return,
+            },
+            #[cfg(unix)]
+            Peer::Unix(ref socket, Some(ref path)) => socket.send_to(&bytes, path).map(|_| ()),
+            #[cfg(unix)]
+            Peer::Unix(_, None) => {
+                println!("Can't reply to an unnamed unix socket");
+                return;
+            }
+        };
+        if let Err(e) = sent {
+            println!("Error replying to {}: {:?}", self, e);
+        }
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Peer::Udp(_, addr) => write!(f, "{}", addr),
+            Peer::Tcp(_, addr, _) => write!(f, "{} (tcp)", addr),
+            #[cfg(unix)]
+            Peer::Unix(_, Some(ref path)) => write!(f, "{}", path.display()),
+            #[cfg(unix)]
+            Peer::Unix(_, None) => write!(f, "(unnamed unix socket)"),
+        }
+    }
+}
+
+// one batch of commands from one packet, on its way to the render loop
+#[derive(Debug)]
+pub struct Envelope {
+    pub from: Peer,
+    pub due: Option<SystemTime>,
+    pub cmds: Vec<Command>,
+    pub bundled: bool, // whether the commands came in a bundle
+}
+
+// counters shared between the network threads and the render loop
+#[derive(Debug, Default)]
+pub struct NetStats {
+    pub packets: AtomicUsize, // packets received, over any transport
+    pub queued: AtomicUsize,  // envelopes waiting for the render loop
+    pub peak: AtomicUsize,    // the most envelopes ever waiting at once
+    pub dropped: AtomicUsize, // envelopes thrown away because the queue was full
+}
+
+impl fmt::Display for NetStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} packets received, {} queued (peak {} of {}), {} dropped",
+            self.packets.load(Ordering::Relaxed),
+            self.queued.load(Ordering::Relaxed),
+            self.peak.load(Ordering::Relaxed),
+            QUEUE_SIZE,
+            self.dropped.load(Ordering::Relaxed)
+        )
+    }
+}
+
+// what every network thread needs to turn packets into envelopes
+#[derive(Clone)]
+struct Sink {
+    tx: SyncSender<Envelope>,
+    stats: Arc<NetStats>,
+    shutdown: Arc<AtomicBool>,
+    coercion: Coercion,
+    error_replies: bool,
+}
+
+// network threads decoding osc packets from any number of sockets into one bounded queue of
+// commands
+pub struct Listener {
+    rx: Receiver<Envelope>,
+    sink: Sink,
+    handles: Vec<JoinHandle<()>>,
+    paths: Vec<PathBuf>, // unix sockets to remove on shutdown
+}
+
+impl Listener {
+    // a listener with nothing to listen to yet. arguments are decoded per $coercion, and with
+    // $error_replies, messages that can't be decoded are answered with an /error message
+    // rather than only logged.
+    pub fn new(coercion: Coercion, error_replies: bool) -> Listener {
+        let (tx, rx) = sync_channel(QUEUE_SIZE);
+        Listener {
+            rx,
+            sink: Sink {
+                tx,
+                stats: Arc::new(NetStats::default()),
+                shutdown: Arc::new(AtomicBool::new(false)),
+                coercion,
+                error_replies,
+            },
+            handles: vec![],
+            paths: vec![],
+        }
+    }
+
+    // spawns a thread reading datagrams from $socket
+    pub fn listen_udp(&mut self, socket: UdpSocket) -> io::Result<()> {
+        // the timeout only bounds how long the thread takes to notice a shutdown
+        socket.set_read_timeout(Some(Duration::from_millis(POLL_TIMEOUT)))?;
+        let sink = self.sink.clone();
+        let handle = thread::Builder::new()
+            .name(format!("osc-udp-{}", socket.local_addr()?))
+            .spawn(move || receive_udp(Arc::new(socket), &sink))?;
+        self.handles.push(handle);
+        Ok(())
+    }
+
+    // binds a unix datagram socket at $path and spawns a thread reading from it. a socket
+    // left behind at $path by an earlier run is replaced, and this one is removed again on
+    // shutdown. a socket something is still bound to is left alone, and is an error.
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, path: &Path) -> io::Result<()> {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            if meta.file_type().is_socket() {
+                // only a socket nothing is bound to any more refuses connections
+                match UnixDatagram::unbound().and_then(|probe| probe.connect(path)) {
+                    Ok(()) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AddrInUse,
+                            format!("{} is in use", path.display()),
+                        ));
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                        fs::remove_file(path)?;
+                    }
+                    Err(_) => (),
+                }
+            }
+        }
+        let socket = UnixDatagram::bind(path)?;
+        self.paths.push(path.to_path_buf());
+        socket.set_read_timeout(Some(Duration::from_millis(POLL_TIMEOUT)))?;
+        let sink = self.sink.clone();
+        let handle = thread::Builder::new()
+            .name(format!("osc-unix-{}", path.display()))
+            .spawn(move || receive_unix(Arc::new(socket), &sink))?;
+        self.handles.push(handle);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen_unix(&mut self, _path: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "unix sockets aren't supported on this platform",
+        ))
+    }
+
+    // spawns a thread accepting connections on $listener, each of which sends packets
+    // delimited per $framing
+    pub fn listen_tcp(&mut self, listener: TcpListener, framing: Framing) -> io::Result<()> {
+        listener.set_nonblocking(true)?;
+        let sink = self.sink.clone();
+        let handle = thread::Builder::new()
+            .name(format!("osc-tcp-{}", listener.local_addr()?))
+            .spawn(move || accept_tcp(&listener, framing, &sink))?;
+        self.handles.push(handle);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> &NetStats {
+        &self.sink.stats
+    }
+
+    // takes every envelope waiting in the queue, without blocking
+    pub fn drain(&self) -> Vec<Envelope> {
+        let envelopes: Vec<Envelope> = self.rx.try_iter().collect();
+        self.sink
+            .stats
+            .queued
+            .fetch_sub(envelopes.len(), Ordering::Relaxed);
+        envelopes
+    }
+
+    // stops every network thread and waits for them to exit
+    pub fn shutdown(self) {
+        self.sink.shutdown.store(true, Ordering::Relaxed);
+        for handle in self.handles {
+            if handle.join().is_err() {
+                println!("Network thread panicked");
+            }
+        }
+        for path in self.paths {
+            if let Err(e) = fs::remove_file(&path) {
+                println!("Couldn't remove {}: {:?}", path.display(), e);
+            }
+        }
+    }
+}
+
+// binds a socket to the port of the multicast group $group and joins it on $interface.
+// the port is shared, so that every previewer on a host can join the same group.
+pub fn bind_multicast(group: SocketAddr, interface: &Interface) -> io::Result<UdpSocket> {
+    let builder = match group {
+        SocketAddr::V4(_) => UdpBuilder::new_v4()?,
+        SocketAddr::V6(_) => UdpBuilder::new_v6()?,
+    };
+    builder.reuse_address(true)?;
+    #[cfg(unix)]
+    builder.reuse_port(true)?;
+    let socket = match group {
+        SocketAddr::V4(addr) => {
+            let socket = builder.bind(("0.0.0.0", addr.port()))?;
+            socket.join_multicast_v4(addr.ip(), &interface.v4)?;
+            socket
+        }
+        SocketAddr::V6(addr) => {
+            builder.only_v6(true)?;
+            let socket = builder.bind(("::", addr.port()))?;
+            socket.join_multicast_v6(addr.ip(), interface.v6)?;
+            socket
+        }
+    };
+    Ok(socket)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+fn receive_udp(socket: Arc<UdpSocket>, sink: &Sink) {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    let registry = Registry::standard();
+    while !sink.shutdown.load(Ordering::Relaxed) {
+        let (size, addr_from) = match socket.recv_from(&mut buf) {
+            Ok(recv) => recv,
+            Err(e) => {
+                if !is_timeout(&e) {
+                    println!("Error receiving from socket: {:?}", e);
+                }
+                continue;
+            }
+        };
+        let peer = Peer::Udp(socket.clone(), addr_from);
+        if !handle_packet(&buf[..size], &peer, &registry, sink) {
+            return;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn receive_unix(socket: Arc<UnixDatagram>, sink: &Sink) {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    let registry = Registry::standard();
+    while !sink.shutdown.load(Ordering::Relaxed) {
+        let (size, addr_from) = match socket.recv_from(&mut buf) {
+            Ok(recv) => recv,
+            Err(e) => {
+                if !is_timeout(&e) {
+                    println!("Error receiving from socket: {:?}", e);
+                }
+                continue;
+            }
+        };
+        let path = addr_from.as_pathname().map(|p| p.to_path_buf());
+        let peer = Peer::Unix(socket.clone(), path);
+        if !handle_packet(&buf[..size], &peer, &registry, sink) {
+            return;
+        }
+    }
+}
+
+// a connection's thread, and whether the connection has closed
+type Connection = (JoinHandle<()>, Arc<AtomicBool>);
+
+fn accept_tcp(listener: &TcpListener, framing: Framing, sink: &Sink) {
+    let mut connections: Vec<Connection> = vec![];
+    while !sink.shutdown.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, addr_from)) => {
+                println!("Accepted connection from {}", addr_from);
+                let sink = sink.clone();
+                let closed = Arc::new(AtomicBool::new(false));
+                let closed_by_thread = closed.clone();
+                let spawned = thread::Builder::new()
+                    .name(format!("osc-tcp-{}", addr_from))
+                    .spawn(move || {
+                        receive_tcp(stream, addr_from, framing, &sink);
+                        closed_by_thread.store(true, Ordering::Relaxed);
+                    });
+                match spawned {
+                    Ok(handle) => connections.push((handle, closed)),
+                    Err(e) => println!("Couldn't start connection thread: {:?}", e),
+                }
+            }
+            Err(ref e) if is_timeout(e) => thread::sleep(Duration::from_millis(POLL_TIMEOUT)),
+            Err(e) => println!("Error accepting connection: {:?}", e),
+        }
+
+        // threads of closed connections are joined as they go, rather than piling up
+        let (closed, open) = connections
+            .into_iter()
+            .partition(|&(_, ref closed)| closed.load(Ordering::Relaxed));
+        join_connections(closed);
+        connections = open;
+    }
+    join_connections(connections);
+}
+
+fn join_connections(connections: Vec<Connection>) {
+    for (handle, _) in connections {
+        if handle.join().is_err() {
+            println!("Connection thread panicked");
+        }
+    }
+}
+
+fn receive_tcp(mut stream: TcpStream, addr_from: SocketAddr, framing: Framing, sink: &Sink) {
+    // on some platforms accepted streams inherit the listener's non-blocking mode. replies
+    // are written from the render loop, which mustn't wait long on a client that has
+    // stopped reading.
+    let writer = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(Duration::from_millis(POLL_TIMEOUT))))
+        .and_then(|_| stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT))))
+        .and_then(|_| stream.try_clone());
+    let peer = match writer {
+        Ok(writer) => Peer::Tcp(Arc::new(Mutex::new(writer)), addr_from, framing),
+        Err(e) => {
+            println!("Error setting up connection from {}: {:?}", addr_from, e);
+            return;
+        }
+    };
+
+    let mut buf = [0u8; 4096];
+    let mut deframer = Deframer::new(framing);
+    let registry = Registry::standard();
+    while !sink.shutdown.load(Ordering::Relaxed) {
+        let size = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(ref e) if is_timeout(e) => continue,
+            Err(e) => {
+                println!("Error receiving from {}: {:?}", peer, e);
+                break;
+            }
+        };
+        for packet in deframer.push(&buf[..size]) {
+            if !handle_packet(&packet, &peer, &registry, sink) {
+                return;
+            }
+        }
+        if deframer.is_broken() {
+            println!("Packet from {} is too large; closing the connection", peer);
+            break;
+        }
+    }
+    println!("Closed connection from {}", addr_from);
+}
+
+// decodes one osc packet and queues its commands for the render loop, returning false once
+// the render loop has gone away
+fn handle_packet(bytes: &[u8], peer: &Peer, registry: &Registry, sink: &Sink) -> bool {
+    let stats = &sink.stats;
+    stats.packets.fetch_add(1, Ordering::Relaxed);
+    println!("Received packet with size {} from: {}", bytes.len(), peer);
+
+    let packet = match rosc::decoder::decode(bytes) {
+        Ok(packet) => packet,
+        Err(e) => {
+            println!("Couldn't decode message: {:?}", e);
+            return true;
+        }
+    };
+
+    for batch in bundle::batches(packet) {
+        let mut cmds = vec![];
+        for msg in batch.messages {
+            log_message(&msg);
+            for decoded in registry.dispatch(&msg, sink.coercion) {
+                match decoded {
+                    Ok(cmd) => cmds.push(cmd),
+                    Err(e) => {
+                        println!("{}", e);
+                        if sink.error_replies {
+                            peer.send(e.to_osc(&msg.addr));
+                        }
+                    }
+                }
+            }
+        }
+        let envelope = Envelope {
+            from: peer.clone(),
+            due: batch.due,
+            cmds,
+            bundled: batch.bundled,
+        };
+        if !queue(envelope, sink) {
+            return false;
+        }
+    }
+    true
+}
+
+// prints $msg as it arrives. messages with a blob or many arguments, like /frame/raw or a
+// long /dsc/voxels, are printed as their address and argument count, since printing every
+// byte of them would hold up the thread receiving them.
+fn log_message(msg: &OscMessage) {
+    let args = msg.args.as_ref().map_or(&[][..], |args| &args[..]);
+    let is_blob = |arg: &OscType| match *arg {
+        OscType::Blob(_) => true,
+        _ => false,
+    };
+    if args.len() > MAX_LOGGED_ARGS || args.iter().any(is_blob) {
+        println!("{:?}\t{} args", msg.addr, args.len());
+    } else {
+        println!("{:?}\t{:?}", msg.addr, msg.args);
+    }
+}
+
+// queues $envelope for the render loop, returning false once the render loop has gone away.
+// datagrams are dropped when the queue is full, but tcp promises delivery in order, so a
+// connection waits for room instead, which stops it reading until the render loop catches
+// up.
+fn queue(mut envelope: Envelope, sink: &Sink) -> bool {
+    let stats = &sink.stats;
+    let reliable = match envelope.from {
+        Peer::Tcp(..) => true,
+        _ => false,
+    };
+    loop {
+        // counted before sending, so the render loop never drains more than was queued
+        let queued = stats.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        match sink.tx.try_send(envelope) {
+            Ok(()) => {
+                if queued > stats.peak.load(Ordering::Relaxed) {
+                    stats.peak.store(queued, Ordering::Relaxed);
+                }
+                return true;
+            }
+            Err(TrySendError::Full(returned)) => {
+                stats.queued.fetch_sub(1, Ordering::Relaxed);
+                if !reliable || sink.shutdown.load(Ordering::Relaxed) {
+                    stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    return true;
+                }
+                envelope = returned;
+                thread::sleep(Duration::from_millis(POLL_TIMEOUT));
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// osc 1.0 address pattern matching. in a pattern, '?' matches any one character, '*' any
+// run of characters, "[abc]" or "[a-z]" one character from a set ("[!abc]" one from outside
+// it), and "{foo,bar}" any of the listed strings. none of them match across a '/'.
+
+// whether $addr uses any pattern syntax, i.e. may match more than itself
+pub fn is_pattern(addr: &str) -> bool {
+    addr.chars().any(|c| "*?[]{}".contains(c))
+}
+
+// whether $pattern matches the address $addr
+pub fn matches(pattern: &str, addr: &str) -> bool {
+    match_from(pattern.as_bytes(), addr.as_bytes())
+}
+
+// one element of a pattern
+enum Token<'a> {
+    Star,           // a run of '*'s
+    One,            // '?'
+    Set(&'a [u8]),  // the contents of "[...]"
+    Alts(&'a [u8]), // the contents of "{...}"
+    Byte(u8),       // a character which only matches itself
+    Never,          // a '[' or '{' which isn't closed, so matches nothing
+}
+
+fn tokens<'a>(p: &'a [u8]) -> Vec<Token<'a>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < p.len() {
+        let (token, len) = match p[i] {
+            b'*' => (Token::Star, 1),
+            b'?' => (Token::One, 1),
+            b'[' | b'{' => {
+                let close = if p[i] == b'[' { b']' } else { b'}' };
+                match p[i..].iter().position(|&c| c == close) {
+                    Some(end) if p[i] == b'[' => (Token::Set(&p[i + 1..i + end]), end + 1),
+                    Some(end) => (Token::Alts(&p[i + 1..i + end]), end + 1),
+                    None => (Token::Never, p.len() - i),
+                }
+            }
+            c => (Token::Byte(c), 1),
+        };
+        i += len;
+        // "**" matches just what "*" does
+        if let (Some(&Token::Star), &Token::Star) = (out.last(), &token) {
+            continue;
+        }
+        out.push(token);
+    }
+    out
+}
+
+// matches token by token, keeping the set of positions in $a the pattern so far can end
+// at, so it takes time in proportion to the lengths of $p and $a however many '*'s there are
+fn match_from(p: &[u8], a: &[u8]) -> bool {
+    let mut reached = vec![false; a.len() + 1];
+    reached[0] = true;
+    for token in tokens(p) {
+        let mut next = vec![false; a.len() + 1];
+        for j in 0..(a.len() + 1) {
+            match token {
+                Token::Star => {
+                    next[j] = reached[j] || (j > 0 && next[j - 1] && a[j - 1] != b'/');
+                }
+                _ if !reached[j] => {}
+                Token::Alts(alts) => {
+                    for alt in alts.split(|&c| c == b',') {
+                        if a[j..].starts_with(alt) {
+                            next[j + alt.len()] = true;
+                        }
+                    }
+                }
+                _ if j == a.len() => {}
+                Token::One => next[j + 1] = a[j] != b'/',
+                Token::Set(set) => next[j + 1] = a[j] != b'/' && in_set(set, a[j]),
+                Token::Byte(c) => next[j + 1] = a[j] == c,
+                Token::Never => {}
+            }
+        }
+        reached = next;
+    }
+    reached[a.len()]
+}
+
+// whether $c is in the contents of a "[...]" set, e.g. "a-z" or "!xyz"
+fn in_set(set: &[u8], c: u8) -> bool {
+    let (negate, set) = match set.first() {
+        Some(&b'!') => (true, &set[1..]),
+        _ => (false, set),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == b'-' {
+            found |= set[i] <= c && c <= set[i + 2];
+            i += 3;
+        } else {
+            found |= set[i] == c;
+            i += 1;
+        }
+    }
+    found != negate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_addresses_match_only_themselves() {
+        assert!(!is_pattern("/dsc/voxel"));
+        assert!(matches("/dsc/voxel", "/dsc/voxel"));
+        assert!(!matches("/dsc/voxel", "/dsc/voxels"));
+        assert!(!matches("/dsc/voxels", "/dsc/voxel"));
+    }
+
+    #[test]
+    fn star_matches_any_run_within_a_part() {
+        assert!(is_pattern("/dsc/*"));
+        assert!(matches("/dsc/*", "/dsc/voxel"));
+        assert!(matches("/dsc/*", "/dsc/"));
+        assert!(matches("/dsc/*/grad", "/dsc/line/grad"));
+        assert!(matches("/*/*", "/get/info"));
+        assert!(matches("/dsc/s*l", "/dsc/shell"));
+        assert!(!matches("/dsc/*", "/dsc/line/grad"));
+        assert!(!matches("/*", "/get/info"));
+        assert!(!matches("/dsc/*l", "/dsc/sphere"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character_but_not_a_slash() {
+        assert!(matches("/get/inf?", "/get/info"));
+        assert!(!matches("/get/inf?", "/get/inf"));
+        assert!(!matches("/get?info", "/get/info"));
+    }
+
+    #[test]
+    fn sets_match_one_character() {
+        assert!(matches("/[gs]et/info", "/get/info"));
+        assert!(matches("/[gs]et/info", "/set/info"));
+        assert!(!matches("/[gs]et/info", "/net/info"));
+        assert!(matches("/layer/[a-c]", "/layer/b"));
+        assert!(!matches("/layer/[a-c]", "/layer/d"));
+        assert!(matches("/layer/[0-9a-f]x", "/layer/ex"));
+        assert!(!matches("/get[/]info", "/get/info"));
+        assert!(!matches("/layer/[a-c", "/layer/a"));
+    }
+
+    #[test]
+    fn negated_sets_match_one_character_outside_them() {
+        assert!(matches("/layer/[!a-c]", "/layer/d"));
+        assert!(!matches("/layer/[!a-c]", "/layer/b"));
+        assert!(matches("/layer/[!xyz]", "/layer/a"));
+        assert!(!matches("/layer/[!xyz]", "/layer/y"));
+        assert!(!matches("/layer/[!xyz]", "/layer/"));
+    }
+
+    #[test]
+    fn alternatives_match_any_listed_string() {
+        assert!(matches("/get/{info,frame}", "/get/info"));
+        assert!(matches("/get/{info,frame}", "/get/frame"));
+        assert!(!matches("/get/{info,frame}", "/get/voxel"));
+        assert!(matches("/dsc/{line,plane}/grad", "/dsc/plane/grad"));
+        assert!(matches("/dsc/voxel{,s}", "/dsc/voxels"));
+        assert!(matches("/dsc/voxel{,s}", "/dsc/voxel"));
+        assert!(!matches("/get/{info", "/get/info"));
+    }
+
+    #[test]
+    fn long_runs_of_stars_match_quickly() {
+        let stars = "*".repeat(1000);
+        assert!(!matches(
+            &format!("/dsc/voxels/{}x", stars),
+            "/dsc/voxels/colored"
+        ));
+        assert!(matches(
+            &format!("/dsc/voxels/{}d", stars),
+            "/dsc/voxels/colored"
+        ));
+        let parts = "*a".repeat(500);
+        assert!(!matches(
+            &format!("/dsc/{}b", parts),
+            &format!("/dsc/{}", "a".repeat(600))
+        ));
+        assert!(matches(
+            &format!("/dsc/{}", parts),
+            &format!("/dsc/{}", "a".repeat(600))
+        ));
+        assert!(matches("/dsc/**/grad", "/dsc/line/grad"));
+        assert!(!matches("/dsc/**", "/dsc/line/grad"));
+    }
+}
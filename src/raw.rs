@@ -0,0 +1,181 @@
+use palette::LinSrgba;
+use std::error::Error;
+use std::str::FromStr;
+
+// the type of each channel of each cell in a raw frame. multi-byte samples are big-endian,
+// like everything else in osc.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sample {
+    U8,
+    U16,
+    F32,
+}
+
+impl Sample {
+    pub fn size(self) -> usize {
+        match self {
+            Sample::U8 => 1,
+            Sample::U16 => 2,
+            Sample::F32 => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sample::U8 => "u8",
+            Sample::U16 => "u16",
+            Sample::F32 => "f32",
+        }
+    }
+
+    // reads one sample from the start of $bytes, scaled to 0..1
+    fn read(self, bytes: &[u8]) -> f32 {
+        match self {
+            Sample::U8 => bytes[0] as f32 / 255.0,
+            Sample::U16 => ((bytes[0] as u16) << 8 | bytes[1] as u16) as f32 / 65535.0,
+            Sample::F32 => f32::from_bits(
+                (bytes[0] as u32) << 24
+                    | (bytes[1] as u32) << 16
+                    | (bytes[2] as u32) << 8
+                    | bytes[3] as u32,
+            ),
+        }
+    }
+
+    // appends $value as one sample. integer samples are clamped to 0..1 first.
+    fn write(self, out: &mut Vec<u8>, value: f32) {
+        let unit = value.max(0.0).min(1.0);
+        match self {
+            Sample::U8 => out.push((unit * 255.0).round() as u8),
+            Sample::U16 => {
+                let n = (unit * 65535.0).round() as u16;
+                out.push((n >> 8) as u8);
+                out.push(n as u8);
+            }
+            Sample::F32 => {
+                let bits = value.to_bits();
+                out.push((bits >> 24) as u8);
+                out.push((bits >> 16) as u8);
+                out.push((bits >> 8) as u8);
+                out.push(bits as u8);
+            }
+        }
+    }
+}
+
+// the layout of a raw frame: the order its channels come in (some arrangement of rgb or
+// rgba, e.g. "bgra") and the type of each. written as CHANNELS_SAMPLE, e.g. "rgba_u8".
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawFormat {
+    pub order: String,
+    pub sample: Sample,
+}
+
+impl RawFormat {
+    // the number of bytes each cell takes up
+    pub fn stride(&self) -> usize {
+        self.order.len() * self.sample.size()
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}_{}", self.order, self.sample.name())
+    }
+
+    // decodes $data into one color per cell, or None if it doesn't hold exactly $len cells.
+    // cells without an alpha channel are opaque.
+    pub fn decode(&self, data: &[u8], len: usize) -> Option<Vec<LinSrgba<f32>>> {
+        if data.len() != len * self.stride() {
+            return None;
+        }
+        let size = self.sample.size();
+        let cells = data
+            .chunks(self.stride())
+            .map(|cell| {
+                let mut rgba = [0.0, 0.0, 0.0, 1.0];
+                for (i, channel) in self.order.chars().enumerate() {
+                    let value = self.sample.read(&cell[i * size..]);
+                    match channel {
+                        'r' => rgba[0] = value,
+                        'g' => rgba[1] = value,
+                        'b' => rgba[2] = value,
+                        _ => rgba[3] = value,
+                    }
+                }
+                LinSrgba::new(rgba[0], rgba[1], rgba[2], rgba[3])
+            })
+            .collect();
+        Some(cells)
+    }
+
+    // encodes $cells in this format, the inverse of decode
+    pub fn encode(&self, cells: &[LinSrgba<f32>]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(cells.len() * self.stride());
+        for clr in cells {
+            for channel in self.order.chars() {
+                let value = match channel {
+                    'r' => clr.red,
+                    'g' => clr.green,
+                    'b' => clr.blue,
+                    _ => clr.alpha,
+                };
+                self.sample.write(&mut out, value);
+            }
+        }
+        out
+    }
+}
+
+impl FromStr for RawFormat {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<RawFormat, Box<Error>> {
+        let mut parts = s.splitn(2, '_');
+        let order = parts.next().unwrap_or("");
+        let sample = match parts.next() {
+            Some("u8") => Sample::U8,
+            Some("u16") => Sample::U16,
+            Some("f32") => Sample::F32,
+            _ => {
+                return Err(From::from(format!("no sample type in raw format {:?}", s)));
+            }
+        };
+
+        let mut sorted: Vec<char> = order.chars().collect();
+        sorted.sort();
+        if sorted != ['b', 'g', 'r'] && sorted != ['a', 'b', 'g', 'r'] {
+            return Err(From::from(format!(
+                "channels in raw format {:?} aren't an ordering of rgb or rgba",
+                s
+            )));
+        }
+
+        Ok(RawFormat {
+            order: order.to_string(),
+            sample,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_is_the_inverse_of_decode() {
+        let data: Vec<u8> = (0..=255).collect();
+        for name in &["rgba_u8", "bgr_u8", "argb_u16", "rgb_f32"] {
+            let format: RawFormat = name.parse().unwrap();
+            let data = &data[..data.len() / format.stride() * format.stride()];
+            let len = data.len() / format.stride();
+            let cells = format.decode(data, len).unwrap();
+            assert_eq!(format.encode(&cells), data, "{}", name);
+        }
+    }
+
+    #[test]
+    fn encode_clamps_integer_samples() {
+        let format: RawFormat = "rgba_u8".parse().unwrap();
+        let cells = [LinSrgba::new(-1.0, 2.0, 0.5, 1.0)];
+        assert_eq!(format.encode(&cells), vec![0, 255, 128, 255]);
+    }
+}
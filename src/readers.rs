@@ -1,87 +1,272 @@
+use blend::BlendMode;
+use error::Error;
 use na::{Point3, Vector3};
 use palette::LinSrgba;
+use raw::RawFormat;
 use rosc::OscType;
-use std::error::Error;
-use std::slice;
-
-pub fn lin_srgba(it: &mut slice::Iter<'_, OscType>) -> Result<LinSrgba<f32>, Box<Error>> {
-    let r: f32 = match it.by_ref().next() {
-        Some(OscType::Float(r)) => *r,
-        _ => {
-            return Err(From::from("No match for <r> in LinSrgba".to_string()));
-        }
-    };
-    let g: f32 = match it.by_ref().next() {
-        Some(OscType::Float(g)) => *g,
-        _ => {
-            return Err(From::from("No match for <g> in LinSrgba".to_string()));
-        }
-    };
-    let b: f32 = match it.by_ref().next() {
-        Some(OscType::Float(b)) => *b,
-        _ => {
-            return Err(From::from("No match for <b> in LinSrgba".to_string()));
+use shaders::LinearGradient;
+
+// which osc types an argument may be sent as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coercion {
+    // only the exact type from the schema: ints for ints, floats for floats
+    Strict,
+    // also any numeric or bool type which converts without loss, or (for colors) clearly
+    // means a float, since many senders can't tell 1 from 1.
+    Lenient,
+}
+
+impl Default for Coercion {
+    fn default() -> Coercion {
+        Coercion::Lenient
+    }
+}
+
+// a cursor over the arguments of a message, remembering how far it has read so that errors
+// can point at the offending argument
+pub struct Args<'a> {
+    args: &'a [OscType],
+    index: usize,
+    coercion: Coercion,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a [OscType], coercion: Coercion) -> Args<'a> {
+        Args {
+            args,
+            index: 0,
+            coercion,
         }
-    };
-    let a: f32 = match it.by_ref().next() {
-        Some(OscType::Float(a)) => *a,
-        _ => {
-            return Err(From::from("No match for <a> in LinSrgba".to_string()));
+    }
+
+    // whether every argument has been read
+    pub fn is_empty(&self) -> bool {
+        self.index >= self.args.len()
+    }
+
+    // takes the next argument, called $name in any error
+    fn next(&mut self, name: &'static str) -> Result<(usize, &'a OscType), Error> {
+        let index = self.index;
+        match self.args.get(index) {
+            Some(arg) => {
+                self.index += 1;
+                Ok((index, arg))
+            }
+            None => Err(Error::MissingArg { index, name }),
         }
-    };
+    }
+}
+
+// a short name for the type of $arg, for error messages
+pub fn type_name(arg: &OscType) -> &'static str {
+    match *arg {
+        OscType::Int(_) => "int",
+        OscType::Float(_) => "float",
+        OscType::String(_) => "string",
+        OscType::Blob(_) => "blob",
+        OscType::Time(..) => "time",
+        OscType::Long(_) => "long",
+        OscType::Double(_) => "double",
+        OscType::Char(_) => "char",
+        OscType::Color(_) => "color",
+        OscType::Midi(_) => "midi",
+        OscType::Bool(_) => "bool",
+        OscType::Nil => "nil",
+        OscType::Inf => "inf",
+    }
+}
+
+fn float(it: &mut Args, name: &'static str) -> Result<f32, Error> {
+    let lenient = it.coercion == Coercion::Lenient;
+    match it.next(name)? {
+        (_, &OscType::Float(f)) => Ok(f),
+        (_, &OscType::Int(n)) if lenient => Ok(n as f32),
+        (_, &OscType::Long(n)) if lenient => Ok(n as f32),
+        (_, &OscType::Double(d)) if lenient => Ok(d as f32),
+        (_, &OscType::Bool(b)) if lenient => Ok(if b { 1.0 } else { 0.0 }),
+        (index, arg) => Err(Error::WrongType {
+            index,
+            name,
+            expected: "float",
+            got: type_name(arg),
+        }),
+    }
+}
+
+// whether $d is a whole number in the range of an i32, so casting it loses nothing. casting
+// any other float to an int is undefined behaviour on the compilers this crate targets.
+fn is_i32(d: f64) -> bool {
+    d.fract() == 0.0 && d >= i32::min_value() as f64 && d <= i32::max_value() as f64
+}
+
+fn integer(it: &mut Args, name: &'static str) -> Result<i32, Error> {
+    let lenient = it.coercion == Coercion::Lenient;
+    match it.next(name)? {
+        (_, &OscType::Int(n)) => Ok(n),
+        (_, &OscType::Long(n)) if lenient && n as i32 as i64 == n => Ok(n as i32),
+        (_, &OscType::Float(f)) if lenient && is_i32(f as f64) => Ok(f as i32),
+        (_, &OscType::Double(d)) if lenient && is_i32(d) => Ok(d as i32),
+        (_, &OscType::Bool(b)) if lenient => Ok(b as i32),
+        (index, arg) => Err(Error::WrongType {
+            index,
+            name,
+            expected: "int",
+            got: type_name(arg),
+        }),
+    }
+}
+
+pub fn lin_srgba(it: &mut Args) -> Result<LinSrgba<f32>, Error> {
+    let r = float(it, "r")?;
+    let g = float(it, "g")?;
+    let b = float(it, "b")?;
+    let a = float(it, "a")?;
     Ok(LinSrgba::new(r, g, b, a))
 }
 
-pub fn dsc_point_3(it: &mut slice::Iter<'_, OscType>) -> Result<Point3<i32>, Box<Error>> {
-    let x: i32 = match it.by_ref().next() {
-        Some(OscType::Int(x)) => *x,
-        _ => {
-            return Err(From::from("No match for <x> in Point3".to_string()));
-        }
-    };
-    let y: i32 = match it.by_ref().next() {
-        Some(OscType::Int(y)) => *y,
-        _ => {
-            return Err(From::from("No match for <y> in Point3".to_string()));
-        }
-    };
-    let z: i32 = match it.by_ref().next() {
-        Some(OscType::Int(z)) => *z,
-        _ => {
-            return Err(From::from("No match for <z> in Point3".to_string()));
-        }
-    };
+pub fn dsc_point_3(it: &mut Args) -> Result<Point3<i32>, Error> {
+    let x = integer(it, "x")?;
+    let y = integer(it, "y")?;
+    let z = integer(it, "z")?;
     Ok(Point3::new(x, y, z))
 }
 
-pub fn dsc_vector_3(it: &mut slice::Iter<'_, OscType>) -> Result<Vector3<i32>, Box<Error>> {
-    let i: i32 = match it.by_ref().next() {
-        Some(OscType::Int(i)) => *i,
-        _ => {
-            return Err(From::from("No match for <i> in Vector3".to_string()));
-        }
-    };
-    let j: i32 = match it.by_ref().next() {
-        Some(OscType::Int(j)) => *j,
-        _ => {
-            return Err(From::from("No match for <j> in Vector3".to_string()));
-        }
-    };
-    let k: i32 = match it.by_ref().next() {
-        Some(OscType::Int(k)) => *k,
-        _ => {
-            return Err(From::from("No match for <k> in Vector3".to_string()));
-        }
-    };
+pub fn dsc_vector_3(it: &mut Args) -> Result<Vector3<i32>, Error> {
+    let i = integer(it, "i")?;
+    let j = integer(it, "j")?;
+    let k = integer(it, "k")?;
     Ok(Vector3::new(i, j, k))
 }
 
-pub fn int(it: &mut slice::Iter<'_, OscType>) -> Result<i32, Box<Error>> {
-    let n: i32 = match it.by_ref().next() {
-        Some(OscType::Int(n)) => *n,
-        _ => {
-            return Err(From::from("No match for <n> in Int".to_string()));
+pub fn int(it: &mut Args) -> Result<i32, Error> {
+    integer(it, "n")
+}
+
+pub fn opacity(it: &mut Args) -> Result<f32, Error> {
+    let index = it.index;
+    let opacity = float(it, "opacity")?;
+    if opacity < 0.0 || opacity > 1.0 {
+        return Err(Error::InvalidValue {
+            index,
+            name: "opacity",
+            value: opacity.to_string(),
+        });
+    }
+    Ok(opacity)
+}
+
+pub fn string(it: &mut Args) -> Result<String, Error> {
+    match it.next("s")? {
+        (_, &OscType::String(ref s)) => Ok(s.clone()),
+        (index, arg) => Err(Error::WrongType {
+            index,
+            name: "s",
+            expected: "string",
+            got: type_name(arg),
+        }),
+    }
+}
+
+pub fn blob(it: &mut Args) -> Result<Vec<u8>, Error> {
+    match it.next("data")? {
+        (_, &OscType::Blob(ref data)) => Ok(data.clone()),
+        (index, arg) => Err(Error::WrongType {
+            index,
+            name: "data",
+            expected: "blob",
+            got: type_name(arg),
+        }),
+    }
+}
+
+pub fn raw_format(it: &mut Args) -> Result<RawFormat, Error> {
+    let index = it.index;
+    let s = string(it)?;
+    s.parse().map_err(|_| Error::InvalidValue {
+        index,
+        name: "format",
+        value: s.clone(),
+    })
+}
+
+// a snapshot name, which must be usable as a file name on its own
+pub fn snapshot_name(it: &mut Args) -> Result<String, Error> {
+    let index = it.index;
+    let s = string(it)?;
+    if s.is_empty() || s.starts_with('.') || s.contains('/') || s.contains('\\') {
+        return Err(Error::InvalidValue {
+            index,
+            name: "name",
+            value: format!("{:?}", s),
+        });
+    }
+    Ok(s)
+}
+
+pub fn blend_mode(it: &mut Args) -> Result<BlendMode, Error> {
+    let index = it.index;
+    let s = string(it)?;
+    s.parse().map_err(|_| Error::InvalidValue {
+        index,
+        name: "mode",
+        value: s.clone(),
+    })
+}
+
+pub fn linear_gradient(it: &mut Args) -> Result<LinearGradient, Error> {
+    Ok(LinearGradient {
+        clr1: lin_srgba(it)?,
+        clr2: lin_srgba(it)?,
+        clr_pt: dsc_point_3(it)?,
+        clr_dir: dsc_vector_3(it)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_int(arg: OscType) -> Result<i32, Error> {
+        int(&mut Args::new(&[arg], Coercion::Lenient))
+    }
+
+    #[test]
+    fn whole_floats_in_range_are_ints() {
+        assert_eq!(read_int(OscType::Float(-3.0)), Ok(-3));
+        assert_eq!(
+            read_int(OscType::Double(2147483647.0)),
+            Ok(i32::max_value())
+        );
+        assert_eq!(
+            read_int(OscType::Double(-2147483648.0)),
+            Ok(i32::min_value())
+        );
+        assert_eq!(
+            read_int(OscType::Float(-2147483648.0)),
+            Ok(i32::min_value())
+        );
+    }
+
+    #[test]
+    fn other_floats_are_not_ints() {
+        for &f in &[
+            0.5,
+            1e10,
+            -1e10,
+            2147483648.0,
+            ::std::f32::NAN,
+            ::std::f32::INFINITY,
+        ] {
+            assert!(read_int(OscType::Float(f)).is_err(), "{}", f);
+        }
+        for &d in &[
+            0.5,
+            2147483648.0,
+            -2147483649.0,
+            ::std::f64::NAN,
+            ::std::f64::NEG_INFINITY,
+        ] {
+            assert!(read_int(OscType::Double(d)).is_err(), "{}", d);
         }
-    };
-    Ok(n)
+    }
 }
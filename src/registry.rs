@@ -0,0 +1,544 @@
+use command::Command;
+use error::Error;
+use pattern;
+use readers;
+use readers::{Args, Coercion};
+use rosc::{OscMessage, OscType};
+
+// parses the arguments of a message sent to a registered address
+pub type Decoder = fn(&mut Args) -> Result<Command, Error>;
+
+// a supported osc address: its typetag (the canonical type of each argument, as in the
+// README schema, with "(...)*" marking a group repeated any number of times), what it
+// does, and how to decode it
+pub struct Entry {
+    pub addr: &'static str,
+    pub typetag: &'static str,
+    pub description: &'static str,
+    pub decode: Decoder,
+}
+
+impl Entry {
+    // /schema addr typetag description, describing this entry to a client. the typetag has
+    // a leading comma, as it would in an osc message.
+    pub fn to_osc(&self) -> OscMessage {
+        OscMessage {
+            addr: "/schema".to_string(),
+            args: Some(vec![
+                OscType::String(self.addr.to_string()),
+                OscType::String(format!(",{}", self.typetag)),
+                OscType::String(self.description.to_string()),
+            ]),
+        }
+    }
+}
+
+// every address the previewer understands
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry { entries: vec![] }
+    }
+
+    pub fn register(
+        &mut self,
+        addr: &'static str,
+        typetag: &'static str,
+        description: &'static str,
+        decode: Decoder,
+    ) {
+        self.entries.push(Entry {
+            addr,
+            typetag,
+            description,
+            decode,
+        });
+    }
+
+    pub fn get(&self, addr: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.addr == addr)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    // parses the address and arguments of $msg, accepting argument types per $coercion. an
+    // address of the form /layer/NAME/ADDR is decoded as ADDR, sent to the layer NAME.
+    pub fn decode(&self, msg: &OscMessage, coercion: Coercion) -> Result<Command, Error> {
+        let args: &[OscType] = match msg.args {
+            Some(ref args) => args,
+            None => &[],
+        };
+        let (layer, addr) = split_layer(&msg.addr);
+        match self.get(addr) {
+            Some(entry) => {
+                (entry.decode)(&mut Args::new(args, coercion)).map(|c| on_layer(layer, c))
+            }
+            None => Err(Error::UnknownAddress(msg.addr.clone())),
+        }
+    }
+
+    // parses $msg once for every registered address its address pattern matches, in
+    // registration order. a message whose address isn't a pattern is decoded as by $decode.
+    pub fn dispatch(&self, msg: &OscMessage, coercion: Coercion) -> Vec<Result<Command, Error>> {
+        if !pattern::is_pattern(&msg.addr) {
+            return vec![self.decode(msg, coercion)];
+        }
+        let args: &[OscType] = match msg.args {
+            Some(ref args) => args,
+            None => &[],
+        };
+        let (layer, addr) = split_layer(&msg.addr);
+        let decoded: Vec<Result<Command, Error>> = self
+            .entries
+            .iter()
+            .filter(|e| pattern::matches(addr, e.addr))
+            .map(|e| (e.decode)(&mut Args::new(args, coercion)).map(|c| on_layer(layer, c)))
+            .collect();
+        if decoded.is_empty() {
+            vec![Err(Error::UnknownAddress(msg.addr.clone()))]
+        } else {
+            decoded
+        }
+    }
+
+    // the registry of every built-in command
+    pub fn standard() -> Registry {
+        let mut registry = Registry::new();
+        registry.register("/dsc/voxel", "iiiffff", "paints a single voxel", |it| {
+            Ok(Command::Voxel {
+                pt: readers::dsc_point_3(it)?,
+                clr: readers::lin_srgba(it)?,
+            })
+        });
+
+        registry.register(
+            "/dsc/voxels",
+            "ffff(iii)*",
+            "paints any number of voxels one color",
+            |it| {
+                let clr = readers::lin_srgba(it)?;
+                let mut pts = vec![];
+                while !it.is_empty() {
+                    pts.push(readers::dsc_point_3(it)?);
+                }
+                Ok(Command::Voxels { clr, pts })
+            },
+        );
+
+        registry.register(
+            "/dsc/voxels/colored",
+            "(iiiffff)*",
+            "paints any number of voxels, each its own color",
+            |it| {
+                let mut voxels = vec![];
+                while !it.is_empty() {
+                    let pt = readers::dsc_point_3(it)?;
+                    voxels.push((pt, readers::lin_srgba(it)?));
+                }
+                Ok(Command::VoxelsColored { voxels })
+            },
+        );
+
+        registry.register(
+            "/dsc/line",
+            "iiiiiiffff",
+            "paints a line from a point along a vector",
+            |it| {
+                Ok(Command::Line {
+                    pt: readers::dsc_point_3(it)?,
+                    dir: readers::dsc_vector_3(it)?,
+                    clr: readers::lin_srgba(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/line/grad",
+            "iiiiiiffffffffiiiiii",
+            "paints a line from a point along a vector, with a gradient",
+            |it| {
+                Ok(Command::LineGrad {
+                    pt: readers::dsc_point_3(it)?,
+                    dir: readers::dsc_vector_3(it)?,
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/plane",
+            "iiiiiiiiiffff",
+            "paints the parallelogram spanned by two vectors from a point",
+            |it| {
+                Ok(Command::Plane {
+                    pt: readers::dsc_point_3(it)?,
+                    vec1: readers::dsc_vector_3(it)?,
+                    vec2: readers::dsc_vector_3(it)?,
+                    clr: readers::lin_srgba(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/plane/grad",
+            "iiiiiiiiiffffffffiiiiii",
+            "paints the parallelogram spanned by two vectors from a point, with a gradient",
+            |it| {
+                Ok(Command::PlaneGrad {
+                    pt: readers::dsc_point_3(it)?,
+                    vec1: readers::dsc_vector_3(it)?,
+                    vec2: readers::dsc_vector_3(it)?,
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/frame",
+            "iiiiiiiiiffff",
+            "paints the outline of the parallelogram spanned by two vectors from a point",
+            |it| {
+                Ok(Command::Frame {
+                    anchor: readers::dsc_point_3(it)?,
+                    vec1: readers::dsc_vector_3(it)?,
+                    vec2: readers::dsc_vector_3(it)?,
+                    clr: readers::lin_srgba(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/frame/grad",
+            "iiiiiiiiiffffffffiiiiii",
+            "paints the outline of the parallelogram spanned by two vectors from a point, \
+             with a gradient",
+            |it| {
+                Ok(Command::FrameGrad {
+                    anchor: readers::dsc_point_3(it)?,
+                    vec1: readers::dsc_vector_3(it)?,
+                    vec2: readers::dsc_vector_3(it)?,
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/cuboid",
+            "iiiiiiiiiiiiffff",
+            "paints the parallelepiped spanned by three vectors from a point",
+            |it| {
+                Ok(Command::Cuboid {
+                    pt: readers::dsc_point_3(it)?,
+                    vec1: readers::dsc_vector_3(it)?,
+                    vec2: readers::dsc_vector_3(it)?,
+                    vec3: readers::dsc_vector_3(it)?,
+                    clr: readers::lin_srgba(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/cuboid/grad",
+            "iiiiiiiiiiiiffffffffiiiiii",
+            "paints the parallelepiped spanned by three vectors from a point, with a gradient",
+            |it| {
+                Ok(Command::CuboidGrad {
+                    pt: readers::dsc_point_3(it)?,
+                    vec1: readers::dsc_vector_3(it)?,
+                    vec2: readers::dsc_vector_3(it)?,
+                    vec3: readers::dsc_vector_3(it)?,
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/sphere",
+            "iiiiffff",
+            "paints a solid sphere of a radius around a point",
+            |it| {
+                Ok(Command::Sphere {
+                    center: readers::dsc_point_3(it)?,
+                    p: readers::int(it)?,
+                    clr: readers::lin_srgba(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/sphere/grad",
+            "iiiiffffffffiiiiii",
+            "paints a solid sphere of a radius around a point, with a gradient",
+            |it| {
+                Ok(Command::SphereGrad {
+                    center: readers::dsc_point_3(it)?,
+                    p: readers::int(it)?,
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/shell",
+            "iiiiffff",
+            "paints a hollow sphere of a radius around a point",
+            |it| {
+                Ok(Command::Shell {
+                    center: readers::dsc_point_3(it)?,
+                    p: readers::int(it)?,
+                    clr: readers::lin_srgba(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/dsc/shell/grad",
+            "iiiiffffffffiiiiii",
+            "paints a hollow sphere of a radius around a point, with a gradient",
+            |it| {
+                Ok(Command::ShellGrad {
+                    center: readers::dsc_point_3(it)?,
+                    p: readers::int(it)?,
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register("/fill/solid", "ffff", "paints every voxel", |it| {
+            Ok(Command::FillSolid {
+                clr: readers::lin_srgba(it)?,
+            })
+        });
+
+        registry.register(
+            "/fill/solid/grad",
+            "ffffffffiiiiii",
+            "paints every voxel, with a gradient",
+            |it| {
+                Ok(Command::FillSolidGrad {
+                    grad: readers::linear_gradient(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/blend",
+            "s",
+            "sets the blend mode of later drawing commands",
+            |it| {
+                Ok(Command::Blend {
+                    mode: readers::blend_mode(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/get/voxel",
+            "iii",
+            "replies /voxel with the color of a voxel",
+            |it| {
+                Ok(Command::GetVoxel {
+                    pt: readers::dsc_point_3(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/get/region",
+            "iiiiii",
+            "replies /region with the colors of a box of voxels",
+            |it| {
+                Ok(Command::GetRegion {
+                    pt: readers::dsc_point_3(it)?,
+                    size: readers::dsc_vector_3(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/get/frame",
+            "",
+            "replies /frame with the colors of every voxel",
+            |_| Ok(Command::GetFrame),
+        );
+
+        registry.register(
+            "/get/info",
+            "",
+            "replies /info with the dimensions and blend mode of the cube",
+            |_| Ok(Command::GetInfo),
+        );
+
+        registry.register(
+            "/schema",
+            "",
+            "replies /schema with the address, typetag and description of every command",
+            |_| Ok(Command::Schema),
+        );
+
+        registry.register(
+            "/clear",
+            "",
+            "sets every voxel to transparent black",
+            |_| Ok(Command::Clear),
+        );
+
+        registry.register(
+            "/reset",
+            "",
+            "restores the default rainbow and blend mode, and ends any blackout",
+            |_| Ok(Command::Reset),
+        );
+
+        registry.register(
+            "/blackout",
+            "",
+            "toggles whether the cube is shown, without changing any voxels",
+            |_| Ok(Command::Blackout),
+        );
+
+        registry.register(
+            "/frame/raw",
+            "sb",
+            "replaces every voxel with colors from a blob, laid out per a format tag",
+            |it| {
+                Ok(Command::FrameRaw {
+                    format: readers::raw_format(it)?,
+                    data: readers::blob(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/frame/manual",
+            "",
+            "draws into a back buffer, shown only on /frame/commit or at the end of a bundle",
+            |_| Ok(Command::FrameManual),
+        );
+
+        registry.register(
+            "/frame/commit",
+            "",
+            "shows everything drawn so far, when drawing into a back buffer",
+            |_| Ok(Command::FrameCommit),
+        );
+
+        registry.register(
+            "/frame/auto",
+            "",
+            "shows every command as soon as it is applied (the default)",
+            |_| Ok(Command::FrameAuto),
+        );
+
+        registry.register(
+            "/undo",
+            "",
+            "reverts the voxels changed by the latest command which changed any",
+            |_| Ok(Command::Undo),
+        );
+
+        registry.register(
+            "/redo",
+            "",
+            "reapplies the latest command undone, unless something has been drawn since",
+            |_| Ok(Command::Redo),
+        );
+
+        registry.register(
+            "/snapshot/save",
+            "s",
+            "saves a layer's voxels to the snapshot file of the given name",
+            |it| {
+                Ok(Command::SnapshotSave {
+                    name: readers::snapshot_name(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/snapshot/load",
+            "s",
+            "replaces a layer's voxels with those in the snapshot file of the given name",
+            |it| {
+                Ok(Command::SnapshotLoad {
+                    name: readers::snapshot_name(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/opacity",
+            "f",
+            "sets how opaque a layer is, from 0 to 1",
+            |it| {
+                Ok(Command::Opacity {
+                    opacity: readers::opacity(it)?,
+                })
+            },
+        );
+
+        registry.register(
+            "/composite",
+            "s",
+            "sets the blend mode a layer is composited onto the layers below it with",
+            |it| {
+                Ok(Command::Composite {
+                    mode: readers::blend_mode(it)?,
+                })
+            },
+        );
+
+        registry.register("/visible", "i", "shows (1) or hides (0) a layer", |it| {
+            Ok(Command::Visible {
+                visible: readers::int(it)? != 0,
+            })
+        });
+
+        registry.register(
+            "/z",
+            "i",
+            "sets where a layer is in the stack; higher layers are composited on top",
+            |it| {
+                Ok(Command::ZOrder {
+                    z: readers::int(it)?,
+                })
+            },
+        );
+
+        registry
+    }
+}
+
+// splits an address of the form /layer/NAME/ADDR into NAME and /ADDR. any other address has
+// no layer.
+fn split_layer(addr: &str) -> (Option<&str>, &str) {
+    if addr.starts_with("/layer/") {
+        let rest = &addr["/layer".len()..];
+        match rest[1..].find('/') {
+            Some(0) | None => (),
+            Some(slash) => return (Some(&rest[1..slash + 1]), &rest[slash + 1..]),
+        }
+    }
+    (None, addr)
+}
+
+fn on_layer(layer: Option<&str>, cmd: Command) -> Command {
+    match layer {
+        Some(name) => Command::Layer {
+            name: name.to_string(),
+            cmd: Box::new(cmd),
+        },
+        None => cmd,
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
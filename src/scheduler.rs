@@ -0,0 +1,159 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+// how many items are held at once by default, and how far ahead of time, in seconds
+pub static DEFAULT_CAPACITY: usize = 4096;
+pub static DEFAULT_LEAD: u64 = 60;
+
+// holds items until their due time. items due at the same time come out in the order they
+// were pushed. it holds at most $capacity items, none due more than $lead from when they
+// were pushed, so whoever sends them can't make it grow without end; the rest are dropped.
+pub struct Scheduler<T> {
+    heap: BinaryHeap<Entry<T>>,
+    seq: u64,
+    capacity: usize,
+    lead: Duration,
+    dropped: usize, // items thrown away for being over capacity or too far ahead
+}
+
+struct Entry<T> {
+    due: SystemTime,
+    seq: u64,
+    item: T,
+}
+
+impl<T> Scheduler<T> {
+    pub fn new(capacity: usize, lead: Duration) -> Scheduler<T> {
+        Scheduler {
+            heap: BinaryHeap::new(),
+            seq: 0,
+            capacity,
+            lead,
+            dropped: 0,
+        }
+    }
+
+    // holds $item until $due, or drops it if $capacity items are already held or $due is
+    // more than $lead after $now
+    pub fn push(&mut self, now: SystemTime, due: SystemTime, item: T) {
+        let too_far = due
+            .duration_since(now)
+            .map_or(false, |ahead| ahead > self.lead);
+        if too_far || self.heap.len() >= self.capacity {
+            self.dropped += 1;
+            return;
+        }
+        self.heap.push(Entry {
+            due,
+            seq: self.seq,
+            item,
+        });
+        self.seq += 1;
+    }
+
+    // removes and returns every item due at or before $now, earliest first
+    pub fn pop_due(&mut self, now: SystemTime) -> Vec<T> {
+        let mut due = vec![];
+        while self.heap.peek().map_or(false, |e| e.due <= now) {
+            if let Some(e) = self.heap.pop() {
+                due.push(e.item);
+            }
+        }
+        due
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Scheduler<T> {
+        Scheduler::new(DEFAULT_CAPACITY, Duration::from_secs(DEFAULT_LEAD))
+    }
+}
+
+impl<T> fmt::Display for Scheduler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} held (at most {}, up to {}s ahead), {} dropped",
+            self.heap.len(),
+            self.capacity,
+            self.lead.as_secs(),
+            self.dropped
+        )
+    }
+}
+
+// BinaryHeap is a max-heap, so entries are ordered in reverse to pop the earliest first
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Entry<T>) -> Ordering {
+        (other.due, other.seq).cmp(&(self.due, self.seq))
+    }
+}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Entry<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Entry<T>) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn items_come_out_earliest_first_then_in_push_order() {
+        let mut scheduler = Scheduler::new(10, Duration::from_secs(100));
+        scheduler.push(at(0), at(3), "c1");
+        scheduler.push(at(0), at(1), "a");
+        scheduler.push(at(0), at(3), "c2");
+        scheduler.push(at(0), at(2), "b1");
+        scheduler.push(at(0), at(2), "b2");
+        scheduler.push(at(0), at(3), "c3");
+        assert_eq!(scheduler.pop_due(at(0)), Vec::<&str>::new());
+        assert_eq!(scheduler.pop_due(at(2)), vec!["a", "b1", "b2"]);
+        assert_eq!(scheduler.len(), 3);
+        assert_eq!(scheduler.pop_due(at(10)), vec!["c1", "c2", "c3"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn items_over_capacity_or_too_far_ahead_are_dropped() {
+        let mut scheduler = Scheduler::new(2, Duration::from_secs(10));
+        scheduler.push(at(5), at(16), "too far");
+        scheduler.push(at(5), at(15), "a");
+        scheduler.push(at(5), at(6), "b");
+        scheduler.push(at(5), at(7), "full");
+        assert_eq!(scheduler.dropped(), 2);
+        assert_eq!(scheduler.pop_due(at(15)), vec!["b", "a"]);
+
+        scheduler.push(at(20), at(21), "c");
+        assert_eq!(scheduler.dropped(), 2);
+        assert_eq!(scheduler.pop_due(at(21)), vec!["c"]);
+    }
+}
@@ -0,0 +1,41 @@
+use na::{Point3, Vector3};
+use palette::LinSrgba;
+use palette::gradient::Gradient;
+
+// maps a cell of a shape to the color it should be painted
+pub type Shader = Fn(Point3<i32>) -> LinSrgba<f32>;
+
+// paints every cell $clr
+pub fn solid(clr: LinSrgba<f32>) -> Box<Shader> {
+    Box::new(move |_cell: Point3<i32>| clr)
+}
+
+// blends from $clr1 to $clr2 along $clr_dir, starting at $clr_pt. cells are projected onto
+// the direction vector, so $clr1 is at $clr_pt and $clr2 is at $clr_pt + $clr_dir.
+pub fn linear_gradient(
+    clr1: LinSrgba<f32>,
+    clr2: LinSrgba<f32>,
+    clr_pt: Point3<i32>,
+    clr_dir: Vector3<i32>,
+) -> Box<Shader> {
+    Box::new(move |cell: Point3<i32>| {
+        let proj: f32 = (cell - clr_pt).dot(&clr_dir) as f32 / clr_dir.dot(&clr_dir) as f32;
+        let grad = Gradient::new(vec![clr1, clr2]);
+        grad.get(proj)
+    })
+}
+
+// the arguments of a linear_gradient, kept as data so commands can be compared and re-sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearGradient {
+    pub clr1: LinSrgba<f32>,
+    pub clr2: LinSrgba<f32>,
+    pub clr_pt: Point3<i32>,
+    pub clr_dir: Vector3<i32>,
+}
+
+impl LinearGradient {
+    pub fn shader(&self) -> Box<Shader> {
+        linear_gradient(self.clr1, self.clr2, self.clr_pt, self.clr_dir)
+    }
+}
@@ -0,0 +1,218 @@
+// the snapshot file format, version 1. every number is big-endian.
+//
+//   magic     4 bytes   "VXPS"
+//   version   u32       1
+//   width     i32
+//   height    i32
+//   depth     i32
+//   cells     width * height * depth cells, each r g b a as f32, in the x-major order of
+//             Grid::cells (z changes fastest, then y, then x)
+//
+// later versions will bump $version; readers reject versions they don't know.
+
+use error::Error;
+use grid::Grid;
+use palette::LinSrgba;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use voxel_buffer::VoxelBuffer;
+use writers;
+
+pub static MAGIC: &'static [u8; 4] = b"VXPS";
+pub static VERSION: u32 = 1;
+pub static EXTENSION: &'static str = "voxels";
+static HEADER_LEN: usize = 20;
+
+fn push_u32(out: &mut Vec<u8>, n: u32) {
+    out.push((n >> 24) as u8);
+    out.push((n >> 16) as u8);
+    out.push((n >> 8) as u8);
+    out.push(n as u8);
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+// encodes $buffer as a snapshot file
+pub fn encode(buffer: &VoxelBuffer) -> Vec<u8> {
+    let grid = buffer.grid();
+    let mut out = Vec::with_capacity(HEADER_LEN + grid.len() * 16);
+    out.extend_from_slice(MAGIC);
+    push_u32(&mut out, VERSION);
+    push_u32(&mut out, grid.width as u32);
+    push_u32(&mut out, grid.height as u32);
+    push_u32(&mut out, grid.depth as u32);
+    for &clr in buffer.cells() {
+        writers::rgba_bytes(&mut out, clr);
+    }
+    out
+}
+
+// decodes a snapshot file, or says what's wrong with it
+pub fn decode(data: &[u8]) -> Result<VoxelBuffer, String> {
+    if data.len() < HEADER_LEN || &data[0..4] != MAGIC {
+        return Err("not a snapshot file".to_string());
+    }
+    let version = read_u32(&data[4..]);
+    if version != VERSION {
+        return Err(format!("unsupported snapshot version {}", version));
+    }
+    let grid = Grid::new(
+        read_u32(&data[8..]) as i32,
+        read_u32(&data[12..]) as i32,
+        read_u32(&data[16..]) as i32,
+    );
+    let len = (grid.width as usize)
+        .checked_mul(grid.height as usize)
+        .and_then(|n| n.checked_mul(grid.depth as usize));
+    // Grid::len counts cells as an i32, so no grid can hold more than that
+    let len = match len {
+        Some(len)
+            if grid.width > 0
+                && grid.height > 0
+                && grid.depth > 0
+                && len <= i32::max_value() as usize =>
+        {
+            len
+        }
+        _ => {
+            return Err(format!(
+                "invalid size {}x{}x{}",
+                grid.width, grid.height, grid.depth
+            ))
+        }
+    };
+    let cells = &data[HEADER_LEN..];
+    if Some(cells.len()) != len.checked_mul(16) {
+        return Err(format!(
+            "{} bytes of voxels, not {} for {}x{}x{}",
+            cells.len(),
+            len * 16,
+            grid.width,
+            grid.height,
+            grid.depth
+        ));
+    }
+
+    let mut buffer = VoxelBuffer::new(grid, LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+    for (clr, bytes) in buffer.cells_mut().iter_mut().zip(cells.chunks(16)) {
+        *clr = LinSrgba::new(
+            f32::from_bits(read_u32(&bytes[0..])),
+            f32::from_bits(read_u32(&bytes[4..])),
+            f32::from_bits(read_u32(&bytes[8..])),
+            f32::from_bits(read_u32(&bytes[12..])),
+        );
+    }
+    Ok(buffer)
+}
+
+fn file_error(path: &Path, reason: String) -> Error {
+    Error::File {
+        path: path.display().to_string(),
+        reason,
+    }
+}
+
+// writes $buffer to $path, making its directory if need be. the file is written alongside
+// and then renamed into place, so a crash never leaves a half-written snapshot behind.
+pub fn save(path: &Path, buffer: &VoxelBuffer) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| file_error(dir, e.to_string()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    let written = fs::File::create(&tmp)
+        .and_then(|mut f| {
+            f.write_all(&encode(buffer))?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    written.map_err(|e| file_error(path, e.to_string()))
+}
+
+pub fn load(path: &Path) -> Result<VoxelBuffer, Error> {
+    let data = fs::read(path).map_err(|e| file_error(path, e.to_string()))?;
+    decode(&data).map_err(|reason| file_error(path, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn buffer() -> VoxelBuffer {
+        let mut buffer = VoxelBuffer::new(Grid::new(2, 3, 4), LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+        for (i, clr) in buffer.cells_mut().iter_mut().enumerate() {
+            *clr = LinSrgba::new(i as f32, -0.5, 1e-3, 1.0);
+        }
+        buffer
+    }
+
+    // a header for a snapshot of $version and size, with no cells after it
+    fn header(version: u32, width: u32, height: u32, depth: u32) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for &n in &[version, width, height, depth] {
+            push_u32(&mut out, n);
+        }
+        out
+    }
+
+    #[test]
+    fn round_trips() {
+        let data = encode(&buffer());
+        assert_eq!(data.len(), HEADER_LEN + 2 * 3 * 4 * 16);
+        assert_eq!(&data[..HEADER_LEN], &header(VERSION, 2, 3, 4)[..]);
+        assert_eq!(decode(&data), Ok(buffer()));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(decode(b"").is_err());
+        assert!(decode(&header(VERSION, 1, 1, 1)[..HEADER_LEN - 1]).is_err());
+        let mut data = encode(&buffer());
+        data[0] = b'X';
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut data = encode(&buffer());
+        data[..HEADER_LEN].copy_from_slice(&header(VERSION + 1, 2, 3, 4));
+        assert_eq!(
+            decode(&data),
+            Err(format!("unsupported snapshot version {}", VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        for &(w, h, d) in &[(0, 1, 1), (1, 0, 1), (1, 1, 0), (0xffff_ffff, 1, 1)] {
+            assert!(decode(&header(VERSION, w, h, d)).is_err());
+        }
+        // more cells than an i32 counts, however the file ends
+        assert!(decode(&header(VERSION, 1 << 16, 1 << 16, 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_the_wrong_number_of_cells() {
+        let data = encode(&buffer());
+        assert!(decode(&data[..data.len() - 16]).is_err());
+        assert!(decode(&data[..data.len() - 1]).is_err());
+        let mut longer = data.clone();
+        longer.extend(&[0; 16]);
+        assert!(decode(&longer).is_err());
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let dir = env::temp_dir().join(format!("voxel-preview-snapshot-{}", process::id()));
+        let path = dir.join("nested").join("a.voxels");
+        save(&path, &buffer()).unwrap();
+        assert_eq!(load(&path), Ok(buffer()));
+        assert!(!path.with_extension("tmp").exists());
+        assert!(load(&dir.join("missing.voxels")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+use command::Command;
+use error::Error;
+use grid::Grid;
+use history::{Edit, History};
+use layer;
+use layer::Layer;
+use palette::LinSrgba;
+use pattern;
+use registry::Registry;
+use rosc::{OscMessage, OscType};
+use snapshot;
+use std::path::PathBuf;
+use voxel_buffer::VoxelBuffer;
+
+// the layer commands draw into when they aren't sent to any other
+pub static BASE_LAYER: &'static str = "base";
+
+// everything commands can change: the layers of voxels and how they're composited, and
+// whether anything is shown at all
+pub struct State {
+    // the base layer first, then every other in the order it was made
+    pub layers: Vec<Layer>,
+    pub blackout: bool,
+    // when drawing is double-buffered, the composited layers as of the last commit.
+    // commands always draw into the layers, so in this mode they are the back buffer.
+    pub front: Option<VoxelBuffer>,
+    pub history: History,
+    pub snapshot_dir: PathBuf, // where /snapshot/save and /snapshot/load keep their files
+}
+
+impl State {
+    pub fn new(buffer: VoxelBuffer) -> State {
+        State {
+            layers: vec![Layer::new(BASE_LAYER, buffer)],
+            blackout: false,
+            front: None,
+            history: History::default(),
+            snapshot_dir: PathBuf::from("snapshots"),
+        }
+    }
+
+    pub fn grid(&self) -> Grid {
+        self.layers[0].buffer.grid()
+    }
+
+    // what the cube should show right now
+    pub fn frame(&self) -> VoxelBuffer {
+        if self.blackout {
+            VoxelBuffer::new(self.grid(), LinSrgba::new(0.0, 0.0, 0.0, 1.0))
+        } else {
+            match self.front {
+                Some(ref front) => front.clone(),
+                None => layer::flatten(&self.layers, self.grid()),
+            }
+        }
+    }
+
+    // when double-buffered, shows everything drawn so far. the layers keep their contents,
+    // so the next frame can be drawn on top of this one.
+    pub fn commit(&mut self) {
+        if self.front.is_some() {
+            self.front = Some(layer::flatten(&self.layers, self.grid()));
+        }
+    }
+
+    // applies $cmd, returning any replies meant for its sender, and records the voxels it
+    // changed as one operation in the history. commands not sent to a layer apply to the
+    // base layer.
+    pub fn apply(&mut self, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+        let result = self.apply_unrecorded(cmd);
+        let op = self
+            .layers
+            .iter_mut()
+            .map(|l| Edit {
+                layer: l.name.clone(),
+                changes: l.take_changes(),
+            })
+            .filter(|e| !e.changes.is_empty())
+            .collect();
+        self.history.record(op);
+        result
+    }
+
+    fn apply_unrecorded(&mut self, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+        match *cmd {
+            Command::GetInfo => Ok(vec![self.info_reply()]),
+            Command::Schema => Ok(Registry::standard()
+                .entries()
+                .iter()
+                .map(|e| e.to_osc())
+                .collect()),
+            Command::Reset => {
+                self.layers = vec![Layer::new(BASE_LAYER, VoxelBuffer::rainbow(self.grid()))];
+                self.blackout = false;
+                self.front = None;
+                self.history.clear();
+                Ok(vec![])
+            }
+            Command::Blackout => {
+                self.blackout = !self.blackout;
+                Ok(vec![])
+            }
+            Command::FrameManual => {
+                if self.front.is_none() {
+                    self.front = Some(layer::flatten(&self.layers, self.grid()));
+                }
+                Ok(vec![])
+            }
+            Command::FrameCommit => {
+                self.commit();
+                Ok(vec![])
+            }
+            Command::FrameAuto => {
+                self.front = None;
+                Ok(vec![])
+            }
+            Command::Undo => {
+                self.undo();
+                Ok(vec![])
+            }
+            Command::Redo => {
+                self.redo();
+                Ok(vec![])
+            }
+            // sent to no layer in particular, queries are about what the cube shows
+            Command::GetVoxel { .. } | Command::GetRegion { .. } | Command::GetFrame => {
+                layer::query(&self.frame(), cmd)
+            }
+            Command::Layer { ref name, ref cmd } => self.apply_to_layers(name, cmd),
+            _ => self.apply_to_index(0, cmd),
+        }
+    }
+
+    // applies $cmd to the layer called $name, or to every layer matching $name if it is a
+    // pattern. commands about the whole cube apply as if sent without a layer.
+    fn apply_to_layers(&mut self, name: &str, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+        match *cmd {
+            Command::GetInfo
+            | Command::Schema
+            | Command::Blackout
+            | Command::FrameManual
+            | Command::FrameCommit
+            | Command::FrameAuto
+            | Command::Undo
+            | Command::Redo
+            | Command::Layer { .. } => return self.apply_unrecorded(cmd),
+            _ => (),
+        }
+
+        let names: Vec<String> = if pattern::is_pattern(name) {
+            self.layers
+                .iter()
+                .filter(|l| pattern::matches(name, &l.name))
+                .map(|l| l.name.clone())
+                .collect()
+        } else {
+            vec![name.to_string()]
+        };
+        let mut replies = vec![];
+        let mut first_err = None;
+        for name in names {
+            match self.apply_to_layer(&name, cmd) {
+                Ok(layer_replies) => replies.extend(layer_replies),
+                Err(e) => first_err = first_err.or(Some(e)),
+            }
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(replies),
+        }
+    }
+
+    // applies $cmd to the layer called $name, making it first if there's none and $cmd
+    // changes voxels or layer settings. /reset removes the layer, or restores the base
+    // layer's rainbow, and forgets its history so undo can't paint old colors onto it or
+    // onto a new layer of the same name.
+    fn apply_to_layer(&mut self, name: &str, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+        let grid = self.grid();
+        if let Command::Reset = *cmd {
+            if name == BASE_LAYER {
+                self.layers[0] = Layer::new(BASE_LAYER, VoxelBuffer::rainbow(grid));
+            } else {
+                self.layers.retain(|l| l.name != name);
+            }
+            self.history.forget(name);
+            return Ok(vec![]);
+        }
+
+        let (index, made) = match self.layers.iter().position(|l| l.name == name) {
+            Some(index) => (index, false),
+            None => {
+                // asking about a layer or saving it mustn't leave it behind
+                match *cmd {
+                    Command::GetVoxel { .. }
+                    | Command::GetRegion { .. }
+                    | Command::GetFrame
+                    | Command::SnapshotSave { .. } => {
+                        return Err(Error::UnknownLayer(name.to_string()));
+                    }
+                    _ => (),
+                }
+                let buffer = VoxelBuffer::new(grid, LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+                self.layers.push(Layer::new(name, buffer));
+                (self.layers.len() - 1, true)
+            }
+        };
+        let result = self.apply_to_index(index, cmd);
+        // nor must loading it from a snapshot which can't be loaded
+        if let (true, &Err(_), &Command::SnapshotLoad { .. }) = (made, &result, cmd) {
+            self.layers.pop();
+        }
+        result
+    }
+
+    // applies $cmd to the layer at $index. snapshots are handled here rather than by the
+    // layer, since only the state knows where their files go.
+    fn apply_to_index(&mut self, index: usize, cmd: &Command) -> Result<Vec<OscMessage>, Error> {
+        match *cmd {
+            Command::SnapshotSave { ref name } => {
+                snapshot::save(&self.snapshot_path(name), &self.layers[index].buffer)?;
+                Ok(vec![])
+            }
+            Command::SnapshotLoad { ref name } => {
+                let path = self.snapshot_path(name);
+                let buffer = snapshot::load(&path)?;
+                let grid = self.grid();
+                if buffer.grid() != grid {
+                    let size = buffer.grid();
+                    return Err(Error::File {
+                        path: path.display().to_string(),
+                        reason: format!(
+                            "snapshot is {}x{}x{}, not {}x{}x{}",
+                            size.width,
+                            size.height,
+                            size.depth,
+                            grid.width,
+                            grid.height,
+                            grid.depth
+                        ),
+                    });
+                }
+                self.layers[index].replace_cells(buffer.cells());
+                Ok(vec![])
+            }
+            _ => self.layers[index].apply(cmd),
+        }
+    }
+
+    fn snapshot_path(&self, name: &str) -> PathBuf {
+        self.snapshot_dir
+            .join(format!("{}.{}", name, snapshot::EXTENSION))
+    }
+
+    // reverts the latest operation in the history
+    fn undo(&mut self) {
+        if let Some(op) = self.history.undo() {
+            for edit in op.iter().rev() {
+                if let Some(layer) = self.layers.iter_mut().find(|l| l.name == edit.layer) {
+                    for change in edit.changes.iter().rev() {
+                        layer.buffer.cells_mut()[change.index] = change.before;
+                    }
+                }
+            }
+        }
+    }
+
+    // reapplies the latest undone operation
+    fn redo(&mut self) {
+        if let Some(op) = self.history.redo() {
+            for edit in op {
+                if let Some(layer) = self.layers.iter_mut().find(|l| l.name == edit.layer) {
+                    for change in &edit.changes {
+                        layer.buffer.cells_mut()[change.index] = change.after;
+                    }
+                }
+            }
+        }
+    }
+
+    // /info width height depth blend, with the blend mode of the base layer
+    fn info_reply(&self) -> OscMessage {
+        let grid = self.grid();
+        OscMessage {
+            addr: "/info".to_string(),
+            args: Some(vec![
+                OscType::Int(grid.width),
+                OscType::Int(grid.height),
+                OscType::Int(grid.depth),
+                OscType::String(self.layers[0].mode.name().to_string()),
+            ]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blend::BlendMode;
+    use na::Point3;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn on_layer(name: &str, cmd: Command) -> Command {
+        Command::Layer {
+            name: name.to_string(),
+            cmd: Box::new(cmd),
+        }
+    }
+
+    fn replies(state: &mut State, cmd: Command) -> Vec<OscMessage> {
+        state.apply(&cmd).unwrap()
+    }
+
+    #[test]
+    fn unprefixed_queries_read_what_the_cube_shows() {
+        let grid = Grid::new(2, 2, 2);
+        let black = LinSrgba::new(0.0, 0.0, 0.0, 1.0);
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let mut state = State::new(VoxelBuffer::new(grid, black));
+        let pt = Point3::new(1, 0, 1);
+        state
+            .apply(&on_layer("fx", Command::Voxel { pt, clr: red }))
+            .unwrap();
+
+        let shown = replies(&mut state, Command::GetVoxel { pt });
+        let on_fx = replies(&mut state, on_layer("fx", Command::GetVoxel { pt }));
+        let on_base = replies(&mut state, on_layer(BASE_LAYER, Command::GetVoxel { pt }));
+        assert_eq!(shown, on_fx);
+        assert_ne!(shown, on_base);
+    }
+
+    #[test]
+    fn manual_frames_show_what_was_drawn_only_once_committed() {
+        let grid = Grid::new(2, 2, 2);
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let (a, b) = (Point3::new(0, 0, 0), Point3::new(1, 1, 1));
+        let mut state = State::new(VoxelBuffer::rainbow(grid));
+        let before = state.frame();
+        state.apply(&Command::FrameManual).unwrap();
+        state.apply(&Command::Voxel { pt: a, clr: red }).unwrap();
+        assert_eq!(state.frame(), before);
+        let drawn = layer::flatten(&state.layers, grid);
+        assert_ne!(drawn, before);
+
+        state.apply(&Command::FrameCommit).unwrap();
+        assert_eq!(state.frame(), drawn);
+        state.apply(&Command::Voxel { pt: b, clr: red }).unwrap();
+        assert_eq!(state.frame(), drawn);
+
+        // ending double buffering shows the back buffer straight away, and from then on
+        // everything drawn shows as soon as it's drawn
+        state.apply(&Command::FrameAuto).unwrap();
+        assert_eq!(state.frame(), layer::flatten(&state.layers, grid));
+        assert_ne!(state.frame(), drawn);
+        state.apply(&Command::Clear).unwrap();
+        assert_eq!(
+            state.frame(),
+            VoxelBuffer::new(grid, LinSrgba::new(0.0, 0.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip_a_voxel_changed_twice() {
+        let grid = Grid::new(2, 2, 2);
+        let pt = Point3::new(1, 0, 1);
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let blue = LinSrgba::new(0.0, 0.0, 1.0, 1.0);
+        let mut state = State::new(VoxelBuffer::rainbow(grid));
+        state
+            .apply(&Command::Blend {
+                mode: BlendMode::Replace,
+            })
+            .unwrap();
+        state
+            .apply(&Command::VoxelsColored {
+                voxels: vec![(pt, red), (Point3::new(0, 1, 0), red), (pt, blue)],
+            })
+            .unwrap();
+        let drawn = state.layers[0].buffer.clone();
+        assert_eq!(drawn.get(pt), Ok(blue));
+
+        state.apply(&Command::Undo).unwrap();
+        assert_eq!(state.layers[0].buffer, VoxelBuffer::rainbow(grid));
+        state.apply(&Command::Redo).unwrap();
+        assert_eq!(state.layers[0].buffer, drawn);
+    }
+
+    #[test]
+    fn undo_forgets_what_was_drawn_before_a_layer_reset() {
+        let grid = Grid::new(2, 2, 2);
+        let red = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let clear = VoxelBuffer::new(grid, LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+        let (a, b) = (Point3::new(0, 0, 0), Point3::new(1, 1, 1));
+        let mut state = State::new(VoxelBuffer::rainbow(grid));
+        state.apply(&Command::Voxel { pt: a, clr: red }).unwrap();
+        state.apply(&on_layer(BASE_LAYER, Command::Reset)).unwrap();
+        state.apply(&Command::Undo).unwrap();
+        assert_eq!(state.layers[0].buffer, VoxelBuffer::rainbow(grid));
+
+        // a layer removed and made again doesn't get the old one's voxels back either
+        state
+            .apply(&on_layer("fx", Command::Voxel { pt: a, clr: red }))
+            .unwrap();
+        state.apply(&on_layer("*", Command::Reset)).unwrap();
+        assert_eq!(state.layers.len(), 1);
+        state
+            .apply(&on_layer("fx", Command::Voxel { pt: b, clr: red }))
+            .unwrap();
+        let drawn = state.layers[1].buffer.clone();
+        state.apply(&Command::Undo).unwrap();
+        state.apply(&Command::Undo).unwrap();
+        assert_eq!(state.layers[1].buffer, clear);
+        state.apply(&Command::Redo).unwrap();
+        state.apply(&Command::Redo).unwrap();
+        assert_eq!(state.layers[1].buffer, drawn);
+        assert_eq!(state.layers[0].buffer, VoxelBuffer::rainbow(grid));
+    }
+
+    #[test]
+    fn queries_to_a_missing_layer_are_errors_and_make_no_layer() {
+        let grid = Grid::new(2, 2, 2);
+        let mut state = State::new(VoxelBuffer::rainbow(grid));
+        for cmd in &[
+            Command::GetVoxel {
+                pt: Point3::new(0, 0, 0),
+            },
+            Command::GetFrame,
+            Command::SnapshotSave {
+                name: "typo".to_string(),
+            },
+        ] {
+            assert_eq!(
+                state.apply(&on_layer("typo", cmd.clone())),
+                Err(Error::UnknownLayer("typo".to_string()))
+            );
+        }
+        assert_eq!(state.layers.len(), 1);
+
+        state.apply(&on_layer("fx", Command::Clear)).unwrap();
+        assert_eq!(state.layers.len(), 2);
+    }
+
+    #[test]
+    fn loading_a_missing_layer_makes_it_only_if_the_snapshot_loads() {
+        let dir = env::temp_dir().join(format!("voxel-preview-state-{}", process::id()));
+        let mut state = State::new(VoxelBuffer::rainbow(Grid::new(2, 2, 2)));
+        state.snapshot_dir = dir.clone();
+        let load = |name: &str| Command::SnapshotLoad {
+            name: name.to_string(),
+        };
+        assert!(state.apply(&on_layer("fx", load("missing"))).is_err());
+        assert_eq!(state.layers.len(), 1);
+
+        state
+            .apply(&Command::SnapshotSave {
+                name: "base".to_string(),
+            })
+            .unwrap();
+        state.apply(&on_layer("fx", load("base"))).unwrap();
+        assert_eq!(state.layers.len(), 2);
+        assert_eq!(state.layers[1].buffer, state.layers[0].buffer);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
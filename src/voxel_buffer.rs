@@ -0,0 +1,61 @@
+use error::Error;
+use grid::Grid;
+use na::Point3;
+use palette::LinSrgba;
+
+// the app-owned color state of the cube, one RGBA value per cell. all painting goes
+// through this buffer; renderers (e.g. the kiss3d grid) only ever read from it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelBuffer {
+    grid: Grid,
+    cells: Vec<LinSrgba<f32>>,
+}
+
+impl VoxelBuffer {
+    // creates a buffer spanning $grid with every cell set to $clr
+    pub fn new(grid: Grid, clr: LinSrgba<f32>) -> VoxelBuffer {
+        VoxelBuffer {
+            grid,
+            cells: vec![clr; grid.len()],
+        }
+    }
+
+    // creates a buffer with the default rainbow coloring
+    pub fn rainbow(grid: Grid) -> VoxelBuffer {
+        let mut buffer = VoxelBuffer::new(grid, LinSrgba::new(0.0, 0.0, 0.0, 1.0));
+        for cell in grid.cells() {
+            let clr = LinSrgba::new(
+                cell.x as f32 / grid.width as f32,
+                cell.y as f32 / grid.height as f32,
+                cell.z as f32 / grid.depth as f32,
+                1.0,
+            );
+            let _ = buffer.set(cell, clr);
+        }
+        buffer
+    }
+
+    pub fn grid(&self) -> Grid {
+        self.grid
+    }
+
+    // every cell, in the order of grid().cells()
+    pub fn cells(&self) -> &[LinSrgba<f32>] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [LinSrgba<f32>] {
+        &mut self.cells
+    }
+
+    pub fn get(&self, pt: Point3<i32>) -> Result<LinSrgba<f32>, Error> {
+        let idx = self.grid.index(pt)?;
+        Ok(self.cells[idx])
+    }
+
+    pub fn set(&mut self, pt: Point3<i32>, clr: LinSrgba<f32>) -> Result<(), Error> {
+        let idx = self.grid.index(pt)?;
+        self.cells[idx] = clr;
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use na::{Point3, Vector3};
+use palette::LinSrgba;
+use rosc::OscType;
+use shaders::LinearGradient;
+
+// the inverse of readers: each appends the osc arguments its reader would consume
+
+pub fn lin_srgba(args: &mut Vec<OscType>, clr: LinSrgba<f32>) {
+    args.push(OscType::Float(clr.red));
+    args.push(OscType::Float(clr.green));
+    args.push(OscType::Float(clr.blue));
+    args.push(OscType::Float(clr.alpha));
+}
+
+pub fn dsc_point_3(args: &mut Vec<OscType>, pt: Point3<i32>) {
+    args.push(OscType::Int(pt.x));
+    args.push(OscType::Int(pt.y));
+    args.push(OscType::Int(pt.z));
+}
+
+pub fn dsc_vector_3(args: &mut Vec<OscType>, v: Vector3<i32>) {
+    args.push(OscType::Int(v.x));
+    args.push(OscType::Int(v.y));
+    args.push(OscType::Int(v.z));
+}
+
+pub fn float(args: &mut Vec<OscType>, f: f32) {
+    args.push(OscType::Float(f));
+}
+
+pub fn int(args: &mut Vec<OscType>, n: i32) {
+    args.push(OscType::Int(n));
+}
+
+pub fn string(args: &mut Vec<OscType>, s: &str) {
+    args.push(OscType::String(s.to_string()));
+}
+
+pub fn blob(args: &mut Vec<OscType>, data: &[u8]) {
+    args.push(OscType::Blob(data.to_vec()));
+}
+
+pub fn linear_gradient(args: &mut Vec<OscType>, grad: &LinearGradient) {
+    lin_srgba(args, grad.clr1);
+    lin_srgba(args, grad.clr2);
+    dsc_point_3(args, grad.clr_pt);
+    dsc_vector_3(args, grad.clr_dir);
+}
+
+// appends $clr to a blob as four big-endian f32s, r g b a
+pub fn rgba_bytes(blob: &mut Vec<u8>, clr: LinSrgba<f32>) {
+    for c in &[clr.red, clr.green, clr.blue, clr.alpha] {
+        let bits = c.to_bits();
+        blob.push((bits >> 24) as u8);
+        blob.push((bits >> 16) as u8);
+        blob.push((bits >> 8) as u8);
+        blob.push(bits as u8);
+    }
+}